@@ -11,7 +11,7 @@ fn criterion_benchmark(c: &mut Criterion) {
 
   c.bench_function("format", |b| {
     b.iter(|| {
-      format(&data, CirruWriterOptions { use_inline: true }).expect("formatted");
+      format(&data, CirruWriterOptions::from(true)).expect("formatted");
     })
   });
 }
@@ -13,6 +13,12 @@ fn criterion_benchmark(c: &mut Criterion) {
         let _ = parse(&content).expect("parsed");
       })
     });
+    #[cfg(feature = "parallel")]
+    c.bench_function("parse large file in parallel", |b| {
+      b.iter(|| {
+        let _ = cirru_parser::parse_parallel(&content).expect("parsed");
+      })
+    });
   } else {
     println!("Failed to read large demo file, skipping benchmark.");
   }
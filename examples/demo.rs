@@ -25,7 +25,7 @@ defn fib (n)
   match parse(&content) {
     Ok(result) => {
       let v = result;
-      let writer_options = CirruWriterOptions { use_inline: false };
+      let writer_options = CirruWriterOptions::from(false);
       let t = format(&v, writer_options).unwrap();
       println!("{}", t.len());
     }
@@ -14,7 +14,7 @@ fn main() -> Result<(), io::Error> {
     let json_str = fs::read_to_string(format!("./tests/writer_data/{file}.json"))?;
     let cirru_str = fs::read_to_string(format!("./tests/writer_cirru/{file}.cirru"))?;
 
-    let writer_options = CirruWriterOptions { use_inline: false };
+    let writer_options = CirruWriterOptions::from(false);
     match from_json_str(&json_str) {
       Ok(tree) => {
         if let Cirru::List(xs) = tree {
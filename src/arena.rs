@@ -0,0 +1,196 @@
+use std::sync::Arc;
+
+use crate::primes::Cirru;
+
+/// Index of a node in a `CirruArena`, stays valid as long as the arena lives
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct NodeId(usize);
+
+impl NodeId {
+  /// position of the node in the arena, nodes are numbered from 0 in the order they were added,
+  /// so it can index side tables kept in a `Vec`
+  pub fn index(self) -> usize {
+    self.0
+  }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum ArenaData {
+  Leaf(Arc<str>),
+  List(Vec<NodeId>),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct ArenaNode {
+  parent: Option<NodeId>,
+  data: ArenaData,
+}
+
+/// Cirru trees with all nodes kept in one arena and addressed by `NodeId`.
+///
+/// Nodes know their parents, so it is possible to walk upward from a node, and ids can key
+/// side tables of types, spans or diagnostics. Nodes are stored flat, so dropping or
+/// converting a deeply nested tree does not recurse.
+///
+/// # Examples
+///
+/// ```
+/// # use cirru_parser::{parse, CirruArena};
+/// let tree = parse("defn f (x)\n  + x 1").unwrap();
+/// let arena = CirruArena::from_cirru(&tree);
+/// let defn = arena.roots()[0];
+/// let body = arena.children(defn)[3];
+/// let x = arena.children(body)[1];
+/// assert_eq!(arena.leaf(x), Some("x"));
+/// assert_eq!(arena.ancestors(x).collect::<Vec<_>>(), vec![body, defn]);
+/// assert_eq!(arena.to_cirru(), tree);
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CirruArena {
+  nodes: Vec<ArenaNode>,
+  roots: Vec<NodeId>,
+}
+
+impl CirruArena {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// copies trees into a new arena, nodes are numbered in pre-order
+  pub fn from_cirru(xs: &[Cirru]) -> Self {
+    let mut arena = Self::new();
+    // lists being copied, with the arena node they are copied into
+    let mut stack: Vec<(std::slice::Iter<Cirru>, Option<NodeId>)> = vec![(xs.iter(), None)];
+    while let Some((iter, parent)) = stack.last_mut() {
+      let parent = *parent;
+      match iter.next() {
+        Some(Cirru::Leaf(s)) => {
+          arena.push_leaf(parent, s.to_owned());
+        }
+        Some(Cirru::List(ys)) => {
+          let id = arena.push_list(parent);
+          stack.push((ys.iter(), Some(id)));
+        }
+        None => {
+          stack.pop();
+        }
+      }
+    }
+    arena
+  }
+
+  /// copies the trees at roots out of the arena
+  pub fn to_cirru(&self) -> Vec<Cirru> {
+    self.roots.iter().map(|id| self.node_to_cirru(*id)).collect()
+  }
+
+  /// copies the tree at `id` out of the arena
+  pub fn node_to_cirru(&self, id: NodeId) -> Cirru {
+    let xs = match &self.nodes[id.0].data {
+      ArenaData::Leaf(s) => return Cirru::Leaf(s.to_owned()),
+      ArenaData::List(xs) => xs,
+    };
+    // lists being copied, with the copied children so far
+    let mut stack: Vec<(std::slice::Iter<NodeId>, Vec<Cirru>)> = vec![(xs.iter(), Vec::with_capacity(xs.len()))];
+    loop {
+      let (iter, acc) = stack.last_mut().expect("stack is not empty");
+      match iter.next().map(|child| &self.nodes[child.0].data) {
+        Some(ArenaData::Leaf(s)) => acc.push(Cirru::Leaf(s.to_owned())),
+        Some(ArenaData::List(ys)) => stack.push((ys.iter(), Vec::with_capacity(ys.len()))),
+        None => {
+          let (_, xs) = stack.pop().expect("stack is not empty");
+          match stack.last_mut() {
+            Some((_, acc)) => acc.push(Cirru::List(xs)),
+            None => return Cirru::List(xs),
+          }
+        }
+      }
+    }
+  }
+
+  fn push_node(&mut self, parent: Option<NodeId>, data: ArenaData) -> NodeId {
+    let id = NodeId(self.nodes.len());
+    match parent {
+      Some(p) => match &mut self.nodes[p.0].data {
+        ArenaData::List(xs) => xs.push(id),
+        ArenaData::Leaf(s) => panic!("can not add a child to leaf {s:?}"),
+      },
+      None => self.roots.push(id),
+    }
+    self.nodes.push(ArenaNode { parent, data });
+    id
+  }
+
+  /// Adds a leaf at the end of the `parent` list, or of roots when `parent` is `None`.
+  /// Panics when `parent` is a leaf.
+  pub fn push_leaf(&mut self, parent: Option<NodeId>, text: impl Into<Arc<str>>) -> NodeId {
+    self.push_node(parent, ArenaData::Leaf(text.into()))
+  }
+
+  /// Adds an empty list at the end of the `parent` list, or of roots when `parent` is `None`.
+  /// Panics when `parent` is a leaf.
+  pub fn push_list(&mut self, parent: Option<NodeId>) -> NodeId {
+    self.push_node(parent, ArenaData::List(vec![]))
+  }
+
+  /// top-level nodes
+  pub fn roots(&self) -> &[NodeId] {
+    &self.roots
+  }
+
+  /// number of nodes
+  pub fn len(&self) -> usize {
+    self.nodes.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.nodes.is_empty()
+  }
+
+  /// all nodes in the order they were added
+  pub fn ids(&self) -> impl Iterator<Item = NodeId> + use<> {
+    (0..self.nodes.len()).map(NodeId)
+  }
+
+  /// the list containing this node, `None` for roots
+  pub fn parent(&self, id: NodeId) -> Option<NodeId> {
+    self.nodes[id.0].parent
+  }
+
+  /// parents of this node, from the closest one up to a root
+  pub fn ancestors(&self, id: NodeId) -> impl Iterator<Item = NodeId> + '_ {
+    std::iter::successors(self.parent(id), |p| self.parent(*p))
+  }
+
+  /// children of a list, empty for a leaf
+  pub fn children(&self, id: NodeId) -> &[NodeId] {
+    match &self.nodes[id.0].data {
+      ArenaData::List(xs) => xs,
+      ArenaData::Leaf(_) => &[],
+    }
+  }
+
+  /// text of a leaf, `None` for a list
+  pub fn leaf(&self, id: NodeId) -> Option<&str> {
+    match &self.nodes[id.0].data {
+      ArenaData::Leaf(s) => Some(s),
+      ArenaData::List(_) => None,
+    }
+  }
+
+  pub fn is_leaf(&self, id: NodeId) -> bool {
+    matches!(self.nodes[id.0].data, ArenaData::Leaf(_))
+  }
+}
+
+impl From<&[Cirru]> for CirruArena {
+  fn from(xs: &[Cirru]) -> Self {
+    Self::from_cirru(xs)
+  }
+}
+
+impl From<&CirruArena> for Vec<Cirru> {
+  fn from(arena: &CirruArena) -> Self {
+    arena.to_cirru()
+  }
+}
@@ -0,0 +1,115 @@
+use std::borrow::Cow;
+use std::fmt;
+
+use crate::primes::Cirru;
+use crate::tree::SugarNode;
+
+/// Cirru tree with leaves borrowed from the code it was parsed from.
+/// Only leaves of strings with escapes, like `"a\"b"`, own their text.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum CirruRef<'a> {
+  Leaf(Cow<'a, str>),
+  List(Vec<CirruRef<'a>>),
+}
+
+impl<'a> CirruRef<'a> {
+  /// copies leaves into a tree of `Cirru`
+  pub fn to_cirru(&self) -> Cirru {
+    match self {
+      Self::Leaf(s) => Cirru::Leaf((**s).into()),
+      Self::List(xs) => Cirru::List(xs.iter().map(Self::to_cirru).collect()),
+    }
+  }
+
+  /// copies borrowed leaves, so the tree no longer depends on the code
+  pub fn into_owned(self) -> CirruRef<'static> {
+    match self {
+      Self::Leaf(s) => CirruRef::Leaf(Cow::Owned(s.into_owned())),
+      Self::List(xs) => CirruRef::List(xs.into_iter().map(Self::into_owned).collect()),
+    }
+  }
+
+  /// text of a leaf, which lives as long as the code when it is borrowed
+  pub fn as_leaf(&self) -> Option<&str> {
+    match self {
+      Self::Leaf(s) => Some(s),
+      Self::List(_) => None,
+    }
+  }
+
+  /// compare it with a reference to string
+  pub fn eq_leaf(&self, s: &str) -> bool {
+    matches!(self, Self::Leaf(l) if l == s)
+  }
+}
+
+impl From<CirruRef<'_>> for Cirru {
+  fn from(value: CirruRef<'_>) -> Self {
+    match value {
+      CirruRef::Leaf(s) => Cirru::Leaf(s.into()),
+      CirruRef::List(xs) => Cirru::List(xs.into_iter().map(Cirru::from).collect()),
+    }
+  }
+}
+
+impl<'a> From<&'a Cirru> for CirruRef<'a> {
+  fn from(value: &'a Cirru) -> Self {
+    match value {
+      Cirru::Leaf(s) => CirruRef::Leaf(Cow::Borrowed(s)),
+      Cirru::List(xs) => CirruRef::List(xs.iter().map(CirruRef::from).collect()),
+    }
+  }
+}
+
+impl PartialEq<Cirru> for CirruRef<'_> {
+  fn eq(&self, other: &Cirru) -> bool {
+    match (self, other) {
+      (Self::Leaf(a), Cirru::Leaf(b)) => **a == **b,
+      (Self::List(xs), Cirru::List(ys)) => xs == ys,
+      _ => false,
+    }
+  }
+}
+
+impl fmt::Display for CirruRef<'_> {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}", self.to_cirru())
+  }
+}
+
+impl<'a> From<Vec<CirruRef<'a>>> for CirruRef<'a> {
+  fn from(value: Vec<CirruRef<'a>>) -> Self {
+    Self::List(value)
+  }
+}
+
+impl SugarNode for CirruRef<'_> {
+  fn is_leaf_text(&self, s: &str) -> bool {
+    self.eq_leaf(s)
+  }
+
+  fn children(&self) -> Option<&[Self]> {
+    match self {
+      Self::List(xs) => Some(xs),
+      Self::Leaf(_) => None,
+    }
+  }
+
+  fn children_mut(&mut self) -> Option<&mut Vec<Self>> {
+    match self {
+      Self::List(xs) => Some(xs),
+      Self::Leaf(_) => None,
+    }
+  }
+
+  fn into_children(self) -> Vec<Self> {
+    match self {
+      Self::List(xs) => xs,
+      Self::Leaf(_) => vec![],
+    }
+  }
+
+  fn wrap_dollar(_dollar: Self, items: Vec<Self>) -> Self {
+    Self::List(items)
+  }
+}
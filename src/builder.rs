@@ -0,0 +1,158 @@
+use std::borrow::Cow;
+use std::cmp::Ordering::*;
+
+use crate::DEFAULT_EXPR_CAPACITY;
+use crate::error::{CirruError, CirruErrorKind, ErrorContext, SourcePos, Span};
+use crate::lexer::{LexToken, Lexer};
+use crate::options::ParseLimits;
+use crate::tree::NestingGuard;
+
+/// Lists being built, the innermost one at the end of `stack`
+struct TreeBuilder<T, F> {
+  /// creates a leaf from text sliced from the code
+  make_leaf: F,
+  acc: Vec<T>,
+  stack: Vec<Vec<T>>,
+  /// whether any token was fed, the outer list is only opened then
+  started: bool,
+  guard: NestingGuard,
+  max_top_level: Option<usize>,
+  /// start of the token being fed
+  pos: SourcePos,
+}
+
+impl<'a, T: From<Vec<T>>, F: FnMut(Cow<'a, str>) -> T> TreeBuilder<T, F> {
+  fn start(&mut self) -> Result<(), CirruError> {
+    if !self.started {
+      self.started = true;
+      self.open()?;
+    }
+    Ok(())
+  }
+
+  fn limit_error(&self, kind: CirruErrorKind) -> CirruError {
+    CirruError::with_context(kind, ErrorContext::new(self.pos, None, "building expressions".to_string()))
+  }
+
+  fn open(&mut self) -> Result<(), CirruError> {
+    if self.stack.is_empty()
+      && let Some(max) = self.max_top_level
+      && self.acc.len() >= max
+    {
+      return Err(self.limit_error(CirruErrorKind::TooManyExpressions { max }));
+    }
+    self.guard.open().map_err(|kind| self.limit_error(kind))?;
+    self.stack.push(Vec::with_capacity(DEFAULT_EXPR_CAPACITY));
+    Ok(())
+  }
+
+  fn close(&mut self) -> Result<(), CirruError> {
+    let xs = self
+      .stack
+      .pop()
+      .ok_or_else(|| CirruError::new(CirruErrorKind::UnexpectedCloseParen))?;
+    self.guard.close();
+    match self.stack.last_mut() {
+      Some(parent) => parent.push(T::from(xs)),
+      None => self.acc.push(T::from(xs)),
+    }
+    Ok(())
+  }
+
+  /// Same as `resolve_indentations` for a line indented by `n` after a line indented by `level`
+  fn indent(&mut self, n: usize, level: usize, first: bool) -> Result<(), CirruError> {
+    match n.cmp(&level) {
+      Greater => {
+        for _ in 0..(n - level) {
+          self.open()?;
+        }
+      }
+      // the first line only opens the outer list
+      Equal if first => (),
+      Less | Equal => {
+        for _ in 0..=(level - n) {
+          self.close()?;
+        }
+        self.open()?;
+      }
+    }
+    Ok(())
+  }
+
+  fn leaf(&mut self, text: Cow<'a, str>) -> Result<(), CirruError> {
+    self.guard.leaf(&text).map_err(|kind| self.limit_error(kind))?;
+    match self.stack.last_mut() {
+      Some(xs) => {
+        xs.push((self.make_leaf)(text));
+        Ok(())
+      }
+      None => Err(CirruError::new(CirruErrorKind::Other(format!("unknown item: Str({text:?})")))),
+    }
+  }
+}
+
+/// Builds a tree while pulling tokens from the lexer, with indentation turned into lists
+/// on the way. The result and errors are the same as lexing, `resolve_indentations` and
+/// `build_exprs_with_limits` in turn, with the limits of the lexer, so errors of lexing anywhere
+/// in the code come first. Errors of limits also carry the position of the token.
+pub(crate) fn build_tree<'a, T: From<Vec<T>>>(
+  mut lexer: Lexer<'a>,
+  make_leaf: impl FnMut(Cow<'a, str>) -> T,
+) -> Result<Vec<T>, CirruError> {
+  let limits = lexer.options().limits;
+  let dollar = lexer.options().wraps_dollar();
+  build_tree_from(std::iter::from_fn(|| lexer.next_token()), &limits, dollar, make_leaf)
+}
+
+/// Same as `build_tree`, with tokens lexed earlier, like the tokens of a chunk.
+/// With `dollar`, each `$` counts as a level of nesting, since `$` will be resolved.
+pub(crate) fn build_tree_from<'a, T: From<Vec<T>>>(
+  mut tokens: impl Iterator<Item = Result<(LexToken<'a>, Span), CirruError>>,
+  limits: &ParseLimits,
+  dollar: bool,
+  make_leaf: impl FnMut(Cow<'a, str>) -> T,
+) -> Result<Vec<T>, CirruError> {
+  let mut builder = TreeBuilder {
+    make_leaf,
+    acc: vec![],
+    stack: Vec::with_capacity(16),
+    started: false,
+    guard: NestingGuard::new(limits.max_depth, dollar),
+    max_top_level: limits.max_top_level,
+    pos: SourcePos::new(1, 1, 0),
+  };
+  let mut level: usize = 0;
+
+  while let Some(item) = tokens.next() {
+    let (token, span) = item?;
+    builder.pos = span.start;
+    let first = !builder.started;
+    let result = builder.start().and_then(|()| match token {
+      LexToken::Open => builder.open(),
+      LexToken::Close => builder.close(),
+      LexToken::Str(s) => builder.leaf(s),
+      LexToken::Indent(n) => {
+        let result = builder.indent(n, level, first);
+        level = n;
+        result
+      }
+    });
+    if let Err(e) = result {
+      // lexing errors later in the code are reported first
+      for item in tokens {
+        item?;
+      }
+      return Err(e);
+    }
+  }
+
+  if builder.started {
+    for _ in 0..=level {
+      builder.close()?;
+    }
+  }
+  if !builder.stack.is_empty() {
+    return Err(CirruError::new(CirruErrorKind::UnexpectedEof));
+  }
+  Ok(builder.acc)
+}
@@ -0,0 +1,223 @@
+//! Splitting code at top-level boundaries, so that top-level expressions can be
+//! parsed on their own and give the same result as parsing the whole file.
+//!
+//! A line that starts at column 0 with a character other than whitespace is a raw
+//! boundary: the lexer is always back to line start there, so code between raw boundaries
+//! lexes the same as in the whole file. Parentheses may still continue over such lines,
+//! like `a (b` followed by `c)`, so pieces are grouped until their parentheses balance.
+//! At the end of a balanced group every list is closed, which is where `parse` starts a
+//! fresh top-level expression too.
+
+use std::ops::Range;
+
+use crate::builder::build_tree_from;
+use crate::error::{CirruError, Span};
+use crate::lexer::{LexToken, Lexer};
+use crate::options::ParseLimits;
+use crate::primes::Cirru;
+use crate::tree::{resolve_comma, resolve_dollar};
+
+/// Error of a chunk, told apart by stage since `parse` reports lexing errors of the whole
+/// file before errors from building the tree.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum ChunkError {
+  Lex(CirruError),
+  Build(CirruError),
+}
+
+/// Top-level expressions parsed from a range of code between two top-level boundaries
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct ParsedChunk {
+  pub range: Range<usize>,
+  /// line where the chunk starts, 1-indexed
+  pub line: usize,
+  pub result: Result<Vec<Cirru>, ChunkError>,
+}
+
+/// Returns the next raw boundary after the line starting at `from`, or `code.len()`.
+pub(crate) fn next_raw_boundary(code: &str, from: usize) -> usize {
+  let bytes = code.as_bytes();
+  let mut idx = from;
+  while let Some(p) = code[idx..].find('\n') {
+    idx += p + 1;
+    match bytes.get(idx) {
+      None => return code.len(),
+      // whitespace may be indentation, depending on the whitespace policy, so the line is not taken as a boundary
+      Some(_) if code[idx..].starts_with(char::is_whitespace) => (),
+      Some(_) => return idx,
+    }
+  }
+  code.len()
+}
+
+/// tokens with their spans, leaves borrow their text from the code
+type Tokens<'a> = Vec<(LexToken<'a>, Span)>;
+
+/// A piece of code between raw boundaries, lexed on its own
+pub(crate) struct LexedPiece<'a> {
+  range: Range<usize>,
+  /// line where the piece starts, 1-indexed
+  line: usize,
+  /// number of newlines in the piece
+  lines: usize,
+  tokens: Result<Tokens<'a>, CirruError>,
+  /// explicit `(` minus explicit `)`
+  balance: i64,
+}
+
+/// lexes `code[range]`, which is between raw boundaries and starts at the start of `line`
+pub(crate) fn lex_piece(code: &str, range: Range<usize>, line: usize) -> LexedPiece<'_> {
+  let lines = count_newlines(&code[range.clone()]);
+  let mut balance: i64 = 0;
+  let mut tokens = Vec::with_capacity((range.end - range.start) >> 4);
+  let mut lexer = Lexer::for_range(code, range.clone(), line);
+  while let Some(item) = lexer.next_token() {
+    match item {
+      Ok((token, span)) => {
+        match token {
+          LexToken::Open => balance += 1,
+          LexToken::Close => balance -= 1,
+          _ => (),
+        }
+        tokens.push((token, span));
+      }
+      Err(e) => {
+        return LexedPiece {
+          range,
+          line,
+          lines,
+          tokens: Err(e),
+          balance: 0,
+        };
+      }
+    }
+  }
+  LexedPiece {
+    range,
+    line,
+    lines,
+    tokens: Ok(tokens),
+    balance,
+  }
+}
+
+pub(crate) fn count_newlines(code: &str) -> usize {
+  code.bytes().filter(|b| *b == b'\n').count()
+}
+
+/// lexes pieces of `code` one by one from `from`, a top-level boundary at the start of `line`
+fn lex_pieces(code: &str, from: usize, line: usize) -> impl Iterator<Item = LexedPiece<'_>> {
+  let mut start = from;
+  let mut line = line;
+  std::iter::from_fn(move || {
+    if start >= code.len() {
+      return None;
+    }
+    let piece = lex_piece(code, start..next_raw_boundary(code, start), line);
+    start = piece.range.end;
+    line += piece.lines;
+    Some(piece)
+  })
+}
+
+/// Tokens of pieces grouped until their parentheses balance
+pub(crate) struct LexedChunk<'a> {
+  range: Range<usize>,
+  line: usize,
+  tokens: Result<Tokens<'a>, CirruError>,
+}
+
+impl LexedChunk<'_> {
+  /// length of code in the chunk
+  #[cfg_attr(not(feature = "parallel"), allow(dead_code))]
+  pub fn size(&self) -> usize {
+    self.range.len()
+  }
+
+  pub fn build(self) -> ParsedChunk {
+    let result = match self.tokens {
+      Ok(xs) => build_chunk(xs),
+      Err(e) => Err(ChunkError::Lex(e)),
+    };
+    ParsedChunk {
+      range: self.range,
+      line: self.line,
+      result,
+    }
+  }
+}
+
+/// groups the next pieces into a chunk, or the pieces left at the end of code
+pub(crate) fn next_chunk<'a>(pieces: &mut impl Iterator<Item = LexedPiece<'a>>) -> Option<LexedChunk<'a>> {
+  let first = pieces.next()?;
+  let mut range = first.range;
+  let mut tokens = first.tokens;
+  let mut balance = first.balance;
+  while balance != 0 {
+    let Some(piece) = pieces.next() else {
+      break;
+    };
+    range.end = piece.range.end;
+    balance += piece.balance;
+    // the first lexing error is kept, the rest of the chunk is still scanned for its end
+    match (&mut tokens, piece.tokens) {
+      (Ok(xs), Ok(mut ys)) => xs.append(&mut ys),
+      (Ok(_), Err(e)) => tokens = Err(e),
+      (Err(_), _) => (),
+    }
+  }
+  Some(LexedChunk {
+    range,
+    line: first.line,
+    tokens,
+  })
+}
+
+/// runs the steps after lexing, same as `parse`, so errors carry the same positions
+fn build_chunk(tokens: Tokens) -> Result<Vec<Cirru>, ChunkError> {
+  let tokens = tokens.into_iter().map(Ok);
+  let mut tree = build_tree_from(tokens, &ParseLimits::default(), true, |s| Cirru::Leaf(s.into())).map_err(ChunkError::Build)?;
+  resolve_dollar(&mut tree);
+  resolve_comma(&mut tree);
+  Ok(tree)
+}
+
+/// Parses chunks of `code` starting from `from`, a top-level boundary at the start of `line`.
+/// At the end of each chunk, `stop` is called with the offset reached, and parsing stops
+/// when it returns `true`, or at the end of code.
+pub(crate) fn parse_chunks(code: &str, from: usize, line: usize, mut stop: impl FnMut(usize) -> bool) -> Vec<ParsedChunk> {
+  let mut chunks = vec![];
+  let mut pieces = lex_pieces(code, from, line);
+  while let Some(chunk) = next_chunk(&mut pieces) {
+    let end = chunk.range.end;
+    chunks.push(chunk.build());
+    if stop(end) {
+      break;
+    }
+  }
+  chunks
+}
+
+/// Combines chunks into the result `parse` gives for the whole code
+pub(crate) fn combine_chunks<'a>(chunks: impl IntoIterator<Item = &'a ParsedChunk> + Clone) -> Result<Vec<Cirru>, CirruError> {
+  let mut build_error: Option<&CirruError> = None;
+  for chunk in chunks.clone() {
+    match &chunk.result {
+      Err(ChunkError::Lex(e)) => return Err(e.to_owned()),
+      Err(ChunkError::Build(e)) => {
+        build_error.get_or_insert(e);
+      }
+      Ok(_) => (),
+    }
+  }
+  if let Some(e) = build_error {
+    return Err(e.to_owned());
+  }
+  let mut tree = vec![];
+  for chunk in chunks {
+    if let Ok(xs) = &chunk.result {
+      tree.extend_from_slice(xs);
+    }
+  }
+  Ok(tree)
+}
@@ -0,0 +1,178 @@
+use std::sync::Arc;
+
+use crate::primes::Cirru;
+
+/// A comment, written as an expression headed by `;` or `;;`, like `; some text`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CirruComment {
+  /// `;` or `;;`
+  pub marker: Arc<str>,
+  /// items after the marker
+  pub body: Vec<Cirru>,
+}
+
+impl CirruComment {
+  /// reads a comment expression, `None` for other nodes
+  pub fn from_expr(x: &Cirru) -> Option<Self> {
+    match x {
+      Cirru::List(xs) => match xs.split_first() {
+        Some((Cirru::Leaf(marker), body)) if xs[0].is_comment() => Some(Self {
+          marker: marker.to_owned(),
+          body: body.to_vec(),
+        }),
+        _ => None,
+      },
+      Cirru::Leaf(_) => None,
+    }
+  }
+
+  /// the comment as an expression again
+  pub fn to_cirru(&self) -> Cirru {
+    let mut xs = Vec::with_capacity(self.body.len() + 1);
+    xs.push(Cirru::Leaf(self.marker.to_owned()));
+    xs.extend(self.body.iter().cloned());
+    Cirru::List(xs)
+  }
+
+  /// items of the body joined by spaces, lists are written like `Display` of `Cirru`
+  pub fn text(&self) -> String {
+    self.body.iter().map(|x| x.to_string()).collect::<Vec<_>>().join(" ")
+  }
+}
+
+/// A node with the comments placed right before it
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CommentedCirru {
+  pub comments: Vec<CirruComment>,
+  pub node: CommentedNode,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CommentedNode {
+  Leaf(Arc<str>),
+  List(CommentedList),
+}
+
+/// Items of a list with comments attached to them. A comment goes with the next item,
+/// comments after the last item are kept in `trailing`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CommentedList {
+  pub items: Vec<CommentedCirru>,
+  pub trailing: Vec<CirruComment>,
+}
+
+/// a list being read by `CommentedList::from_cirru`, with the items left to read
+struct ReadFrame<'a> {
+  items: std::slice::Iter<'a, Cirru>,
+  list: CommentedList,
+  /// comments before the next item
+  comments: Vec<CirruComment>,
+}
+
+impl<'a> ReadFrame<'a> {
+  fn new(items: &'a [Cirru]) -> Self {
+    Self {
+      items: items.iter(),
+      list: CommentedList::default(),
+      comments: vec![],
+    }
+  }
+}
+
+impl CommentedList {
+  /// takes comment expressions out of `xs` and attaches them to their neighbours
+  pub fn from_cirru(xs: &[Cirru]) -> Self {
+    let mut stack = vec![ReadFrame::new(xs)];
+    loop {
+      let frame = stack.last_mut().expect("stack has the outer list");
+      let node = match frame.items.next() {
+        Some(x) => {
+          if let Some(comment) = CirruComment::from_expr(x) {
+            frame.comments.push(comment);
+            continue;
+          }
+          match x {
+            Cirru::Leaf(s) => CommentedNode::Leaf(s.to_owned()),
+            Cirru::List(ys) => {
+              stack.push(ReadFrame::new(ys));
+              continue;
+            }
+          }
+        }
+        None => {
+          let mut done = stack.pop().expect("stack has the outer list");
+          done.list.trailing = done.comments;
+          match stack.last() {
+            Some(_) => CommentedNode::List(done.list),
+            None => return done.list,
+          }
+        }
+      };
+      let parent = stack.last_mut().expect("stack has the outer list");
+      parent.list.items.push(CommentedCirru {
+        comments: std::mem::take(&mut parent.comments),
+        node,
+      });
+    }
+  }
+
+  /// the tree without comments
+  pub fn to_cirru(&self) -> Vec<Cirru> {
+    self.write_cirru(false)
+  }
+
+  /// the tree with comments put back as expressions before their items, same as `parse` returns
+  pub fn to_cirru_with_comments(&self) -> Vec<Cirru> {
+    self.write_cirru(true)
+  }
+
+  fn write_cirru(&self, with_comments: bool) -> Vec<Cirru> {
+    // lists being written, with the items left to write and the nodes written so far
+    let mut stack = vec![(self, self.items.iter(), vec![])];
+    loop {
+      let (list, items, written) = stack.last_mut().expect("stack has the outer list");
+      let node = match items.next() {
+        Some(x) => {
+          if with_comments {
+            written.extend(x.comments.iter().map(CirruComment::to_cirru));
+          }
+          match &x.node {
+            CommentedNode::Leaf(s) => Cirru::Leaf(s.to_owned()),
+            CommentedNode::List(ys) => {
+              stack.push((ys, ys.items.iter(), vec![]));
+              continue;
+            }
+          }
+        }
+        None => {
+          if with_comments {
+            written.extend(list.trailing.iter().map(CirruComment::to_cirru));
+          }
+          let (_, _, done) = stack.pop().expect("stack has the outer list");
+          match stack.last() {
+            Some(_) => Cirru::List(done),
+            None => return done,
+          }
+        }
+      };
+      stack.last_mut().expect("stack has the outer list").2.push(node);
+    }
+  }
+}
+
+fn is_comment_expr(x: &Cirru) -> bool {
+  matches!(x, Cirru::List(xs) if xs.first().is_some_and(Cirru::is_comment))
+}
+
+/// removes comment expressions at all levels
+pub(crate) fn strip_comments(xs: &mut Vec<Cirru>) {
+  let mut lists: Vec<&mut Vec<Cirru>> = vec![xs];
+  while let Some(ys) = lists.pop() {
+    ys.retain(|y| !is_comment_expr(y));
+    for y in ys {
+      if let Cirru::List(zs) = y {
+        lists.push(zs);
+      }
+    }
+  }
+}
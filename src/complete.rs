@@ -0,0 +1,97 @@
+use crate::builder;
+use crate::error::{CirruError, ErrorContext, SourcePos};
+use crate::lexer::{LexToken, Lexer};
+use crate::options::ParseLimits;
+use crate::primes::Cirru;
+
+/// Whether some code is a whole piece of Cirru, see `check_input_complete`
+#[derive(Debug, Clone, PartialEq)]
+pub enum InputStatus {
+  /// parses without errors
+  Complete,
+  /// valid so far, and more input could finish it
+  Incomplete {
+    /// number of `)` needed to close open lists
+    expected_closers: usize,
+    /// code ends inside a string literal
+    open_string: bool,
+  },
+  /// has an error that more input can not fix
+  Invalid(CirruError),
+}
+
+/// Checks whether code typed so far is complete, for a prompt deciding to ask for another line.
+///
+/// Code ending inside a string or with lists left open is `Incomplete`. Newlines are not allowed
+/// in strings, so a string left open on an earlier line is `Invalid`.
+///
+/// # Examples
+///
+/// ```
+/// # use cirru_parser::{check_input_complete, InputStatus};
+/// assert_eq!(check_input_complete("println (add 1 2)"), InputStatus::Complete);
+/// assert_eq!(
+///   check_input_complete("println (add 1 (sub 2"),
+///   InputStatus::Incomplete { expected_closers: 2, open_string: false }
+/// );
+/// assert_eq!(
+///   check_input_complete("println \"hello"),
+///   InputStatus::Incomplete { expected_closers: 0, open_string: true }
+/// );
+/// assert!(matches!(check_input_complete("a)"), InputStatus::Invalid(_)));
+/// ```
+pub fn check_input_complete(code: &str) -> InputStatus {
+  let mut lexer = Lexer::new(code);
+  let mut depth: usize = 0;
+  // a `)` with no `(` to close may close the list of its line, so only `parse` can tell
+  let mut stray_close = false;
+  while let Some(item) = lexer.next_token() {
+    match item {
+      Ok((LexToken::Open, _)) => depth += 1,
+      Ok((LexToken::Close, _)) => match depth.checked_sub(1) {
+        Some(d) => depth = d,
+        None => stray_close = true,
+      },
+      Ok(_) => (),
+      // errors at end of code are left by a string that is not closed yet
+      Err(error) if lexer.in_string() && error.context.as_ref().is_some_and(|c| c.pos.offset == code.len()) => {
+        return InputStatus::Incomplete {
+          expected_closers: depth,
+          open_string: true,
+        };
+      }
+      Err(error) => return InputStatus::Invalid(error),
+    }
+  }
+  if depth > 0 && !stray_close {
+    return InputStatus::Incomplete {
+      expected_closers: depth,
+      open_string: false,
+    };
+  }
+  match parse_located(code) {
+    Ok(()) => InputStatus::Complete,
+    Err(error) => InputStatus::Invalid(error),
+  }
+}
+
+/// Builds the tree like `parse`, errors without a position get the one of the last token read
+fn parse_located(code: &str) -> Result<(), CirruError> {
+  let mut lexer = Lexer::new(code);
+  let mut last = SourcePos::new(1, 1, 0);
+  let tokens = std::iter::from_fn(|| {
+    let item = lexer.next_token()?;
+    if let Ok((_, span)) = &item {
+      last = span.start;
+    }
+    Some(item)
+  });
+  match builder::build_tree_from(tokens, &ParseLimits::default(), true, |s| Cirru::Leaf(s.into())) {
+    Ok(_) => Ok(()),
+    Err(error) if error.context.is_none() => {
+      let error_ctx = ErrorContext::new(last, None, "building expressions".to_string());
+      Err(CirruError::with_context(error.kind, error_ctx))
+    }
+    Err(error) => Err(error),
+  }
+}
@@ -0,0 +1,179 @@
+use std::fmt;
+
+use crate::MAX_NESTING_DEPTH;
+use crate::error::{CirruError, CirruErrorKind, ErrorContext, Span};
+use crate::primes::{Cirru, CirruLexItem};
+use crate::tree::{NestingGuard, resolve_comma, resolve_dollar};
+
+/// How a leaf is written in the source
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CstLeafStyle {
+  /// plain token like `a` or `$`
+  Bare,
+  /// string literal like `"a b"`
+  Quoted,
+}
+
+/// Node of the lossless concrete syntax tree
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CstNode {
+  /// spaces, newlines, indentation and blank lines between tokens
+  Trivia(String),
+  /// an explicit `(`
+  Open,
+  /// an explicit `)`
+  Close,
+  /// a leaf, `raw` is the text as written, `value` is the text after processing escapes
+  Leaf { raw: String, value: String, style: CstLeafStyle },
+  /// an expression from parentheses or indentation, explicit parentheses are kept as children
+  Expr(Vec<CstNode>),
+}
+
+impl CstNode {
+  /// `$` and `,` leaves, which are removed when lowering to `Cirru`
+  pub fn is_sugar_marker(&self) -> bool {
+    matches!(self, Self::Leaf { raw, style: CstLeafStyle::Bare, .. } if raw == "$" || raw == ",")
+  }
+
+  fn write_source(&self, buf: &mut String) {
+    match self {
+      Self::Trivia(s) => buf.push_str(s),
+      Self::Open => buf.push('('),
+      Self::Close => buf.push(')'),
+      Self::Leaf { raw, .. } => buf.push_str(raw),
+      Self::Expr(xs) => {
+        for x in xs {
+          x.write_source(buf);
+        }
+      }
+    }
+  }
+
+  /// returns `None` for trivia and parentheses, which have no counterpart in `Cirru`
+  fn lower(&self) -> Option<Cirru> {
+    match self {
+      Self::Trivia(_) | Self::Open | Self::Close => None,
+      Self::Leaf { value, .. } => Some(Cirru::leaf(value.as_str())),
+      Self::Expr(xs) => Some(Cirru::List(xs.iter().filter_map(Self::lower).collect())),
+    }
+  }
+}
+
+/// Lossless concrete syntax tree of a Cirru file.
+/// Printing it back gives exactly the original code.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CirruCst {
+  pub nodes: Vec<CstNode>,
+}
+
+impl CirruCst {
+  /// the source code this tree was parsed from
+  pub fn to_source(&self) -> String {
+    let mut buf = String::new();
+    for node in &self.nodes {
+      node.write_source(&mut buf);
+    }
+    buf
+  }
+
+  /// lowers to the same tree `parse` returns, with `$` and `,` resolved
+  pub fn lower(&self) -> Vec<Cirru> {
+    let mut tree: Vec<Cirru> = self.nodes.iter().filter_map(CstNode::lower).collect();
+    resolve_dollar(&mut tree);
+    resolve_comma(&mut tree);
+    tree
+  }
+}
+
+impl fmt::Display for CirruCst {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}", self.to_source())
+  }
+}
+
+/// Builds the CST from tokens produced by `resolve_indentations_spanned`.
+/// Text between the spans of real tokens becomes trivia.
+pub(crate) fn build_cst(code: &str, tokens: &[(CirruLexItem, Span)]) -> Result<CirruCst, CirruError> {
+  let mut acc: Vec<CstNode> = vec![];
+  // children of each open expression, with the span of the token that opened it
+  let mut stack: Vec<(Vec<CstNode>, Span)> = Vec::with_capacity(16);
+  let mut last_end: usize = 0;
+  let mut guard = NestingGuard::new(MAX_NESTING_DEPTH, true);
+  let too_deep = |kind, span: &Span| {
+    let error_ctx = ErrorContext::new(span.start, None, "building expressions".to_string());
+    CirruError::with_context(kind, error_ctx)
+  };
+
+  for (token, span) in tokens {
+    let at_top_level = stack.is_empty();
+    let current = stack.last_mut().map_or(&mut acc, |(xs, _)| xs);
+    if !span.is_empty() {
+      if span.start.offset > last_end {
+        current.push(CstNode::Trivia(code[last_end..span.start.offset].to_owned()));
+      }
+      last_end = span.end.offset;
+    }
+
+    match token {
+      CirruLexItem::Open => {
+        guard.open().map_err(|kind| too_deep(kind, span))?;
+        let mut children = vec![];
+        if !span.is_empty() {
+          children.push(CstNode::Open);
+        }
+        stack.push((children, *span));
+      }
+      CirruLexItem::Close => {
+        if !span.is_empty() {
+          current.push(CstNode::Close);
+        }
+        match stack.pop() {
+          None => {
+            let error_ctx = ErrorContext::new(span.start, None, "at top level".to_string());
+            return Err(CirruError::with_context(CirruErrorKind::UnexpectedCloseParen, error_ctx));
+          }
+          Some((children, _)) => {
+            guard.close();
+            stack.last_mut().map_or(&mut acc, |(xs, _)| xs).push(CstNode::Expr(children))
+          }
+        }
+      }
+      CirruLexItem::Str(s) => {
+        if at_top_level {
+          let error_ctx = ErrorContext::new(span.start, None, "at top level".to_string());
+          return Err(CirruError::with_context(
+            CirruErrorKind::Other(format!("unknown item: {token:?}")),
+            error_ctx,
+          ));
+        }
+        guard.leaf(s).map_err(|kind| too_deep(kind, span))?;
+        let raw = &code[span.start.offset..span.end.offset];
+        let style = if raw.starts_with('"') {
+          CstLeafStyle::Quoted
+        } else {
+          CstLeafStyle::Bare
+        };
+        current.push(CstNode::Leaf {
+          raw: raw.to_owned(),
+          value: s.to_owned(),
+          style,
+        });
+      }
+      CirruLexItem::Indent(n) => {
+        return Err(CirruError::new(CirruErrorKind::Other(format!("unknown indent: {n}"))));
+      }
+    }
+  }
+
+  // same as `build_spanned_exprs`, point at the innermost `(` left open
+  if let Some((_, open)) = stack.iter().rev().find(|(_, open)| !open.is_empty()).or(stack.first()) {
+    let error_ctx = ErrorContext::new(open.start, None, "unclosed expression".to_string());
+    return Err(CirruError::with_context(CirruErrorKind::UnexpectedEof, error_ctx));
+  }
+
+  if last_end < code.len() {
+    acc.push(CstNode::Trivia(code[last_end..].to_owned()));
+  }
+
+  Ok(CirruCst { nodes: acc })
+}
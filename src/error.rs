@@ -3,7 +3,7 @@
 use std::fmt;
 
 /// Position information in the source code
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct SourcePos {
   /// Line number (1-indexed)
   pub line: usize,
@@ -25,6 +25,35 @@ impl fmt::Display for SourcePos {
   }
 }
 
+/// A range in the source code, `start` is inclusive and `end` is exclusive
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+  pub start: SourcePos,
+  pub end: SourcePos,
+}
+
+impl Span {
+  pub fn new(start: SourcePos, end: SourcePos) -> Self {
+    Self { start, end }
+  }
+
+  /// a zero-width span, used for nodes that have no text of their own
+  pub fn empty_at(pos: SourcePos) -> Self {
+    Self { start: pos, end: pos }
+  }
+
+  /// `true` when the span covers no text
+  pub fn is_empty(&self) -> bool {
+    self.start.offset == self.end.offset
+  }
+}
+
+impl fmt::Display for Span {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}:{}-{}:{}", self.start.line, self.start.column, self.end.line, self.end.column)
+  }
+}
+
 /// Context information for better error messages
 #[derive(Debug, Clone, PartialEq)]
 pub struct ErrorContext {
@@ -55,16 +84,36 @@ pub enum CirruErrorKind {
   UnexpectedEof,
   /// Unmatched parenthesis
   UnmatchedParen,
-  /// Invalid indentation (odd number of spaces)
-  InvalidIndentation(u8),
+  /// Invalid indentation, with a width that is not a multiple of the indent unit
+  InvalidIndentation(usize),
+  /// Tab in indentation by spaces, or space in indentation by tabs
+  UnexpectedIndentChar(char),
+  /// Whitespace other than space and newline, when it is not allowed
+  UnexpectedWhitespace(char),
+  /// Indentation deeper than the limit set for the lexer
+  IndentationTooDeep { depth: usize, max: usize },
+  /// Lists nested deeper than the limit, counting lists created by `$`
+  NestingTooDeep { depth: usize, max: usize },
+  /// Code longer than the limit, in bytes
+  InputTooLarge { size: usize, max: usize },
+  /// Leaf longer than the limit, in bytes
+  LeafTooLong { len: usize, max: usize },
+  /// More tokens than the limit
+  TooManyTokens { max: usize },
+  /// More top-level expressions than the limit
+  TooManyExpressions { max: usize },
   /// Unexpected newline in string literal
   UnexpectedNewlineInString,
   /// Invalid escape sequence
   InvalidEscape(char),
+  /// Malformed `\u{XXXX}` escape, with the text of the escape read so far
+  InvalidUnicodeEscape(String),
   /// Unexpected closing parenthesis
   UnexpectedCloseParen,
   /// Wrong number of top-level expressions
   WrongExprCount { expected: usize, got: usize },
+  /// Failed to read input from a stream
+  Io(String),
   /// Generic error with custom message
   Other(String),
 }
@@ -75,13 +124,27 @@ impl fmt::Display for CirruErrorKind {
       Self::UnexpectedChar(c) => write!(f, "Unexpected character '{c}'"),
       Self::UnexpectedEof => write!(f, "Unexpected end of file"),
       Self::UnmatchedParen => write!(f, "Unmatched parenthesis"),
-      Self::InvalidIndentation(n) => write!(f, "Invalid indentation (odd number: {n})"),
+      Self::InvalidIndentation(n) => write!(f, "Invalid indentation (width {n} is not a multiple of the indent unit)"),
+      Self::UnexpectedIndentChar(c) => write!(f, "Unexpected {c:?} in indentation"),
+      Self::UnexpectedWhitespace(c) => write!(f, "Unexpected whitespace {c:?}"),
+      Self::IndentationTooDeep { depth, max } => {
+        write!(f, "Indentation too deep ({depth} levels, at most {max} allowed)")
+      }
+      Self::NestingTooDeep { depth, max } => {
+        write!(f, "Expressions nested too deep ({depth} levels, at most {max} allowed)")
+      }
+      Self::InputTooLarge { size, max } => write!(f, "Input too large ({size} bytes, at most {max} allowed)"),
+      Self::LeafTooLong { len, max } => write!(f, "Leaf too long ({len} bytes, at most {max} allowed)"),
+      Self::TooManyTokens { max } => write!(f, "Too many tokens (at most {max} allowed)"),
+      Self::TooManyExpressions { max } => write!(f, "Too many top-level expressions (at most {max} allowed)"),
       Self::UnexpectedNewlineInString => write!(f, "Unexpected newline in string literal"),
       Self::InvalidEscape(c) => write!(f, "Invalid escape sequence '\\{c}'"),
+      Self::InvalidUnicodeEscape(s) => write!(f, "Invalid unicode escape sequence '{s}'"),
       Self::UnexpectedCloseParen => write!(f, "Unexpected closing parenthesis ')'"),
       Self::WrongExprCount { expected, got } => {
         write!(f, "Expected {expected} expression(s), but got {got}")
       }
+      Self::Io(msg) => write!(f, "Failed to read input: {msg}"),
       Self::Other(msg) => write!(f, "{msg}"),
     }
   }
@@ -165,6 +228,18 @@ impl From<CirruError> for String {
   }
 }
 
+/// When recovering, keeps the error so the caller can repair its state and go on,
+/// otherwise returns the error to stop parsing.
+pub(crate) fn report(errors: &mut Option<&mut Vec<CirruError>>, error: CirruError) -> Result<(), CirruError> {
+  match errors {
+    Some(xs) => {
+      xs.push(error);
+      Ok(())
+    }
+    None => Err(error),
+  }
+}
+
 /// Extract a code snippet around the given position
 fn extract_snippet(code: &str, pos: &SourcePos) -> Option<String> {
   let lines: Vec<&str> = code.lines().collect();
@@ -210,6 +285,14 @@ mod tests {
     assert!(display.contains("line 10"));
   }
 
+  #[test]
+  fn test_limit_display() {
+    let err = CirruError::new(CirruErrorKind::LeafTooLong { len: 12, max: 8 });
+    assert_eq!(err.to_string(), "Leaf too long (12 bytes, at most 8 allowed)");
+    let err = CirruError::new(CirruErrorKind::TooManyExpressions { max: 2 });
+    assert_eq!(err.to_string(), "Too many top-level expressions (at most 2 allowed)");
+  }
+
   #[test]
   fn test_snippet_extraction() {
     let code = "line1\nline2\nline3\nline4\nline5";
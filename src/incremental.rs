@@ -0,0 +1,119 @@
+use std::ops::Range;
+
+use crate::chunk::{ParsedChunk, combine_chunks, parse_chunks};
+use crate::error::CirruError;
+use crate::primes::Cirru;
+
+/// A text edit, replacing the bytes in `range` with `text`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TextEdit {
+  pub range: Range<usize>,
+  pub text: String,
+}
+
+impl TextEdit {
+  pub fn new(range: Range<usize>, text: impl Into<String>) -> Self {
+    Self { range, text: text.into() }
+  }
+}
+
+/// Cirru code kept parsed while it is being edited.
+///
+/// Code is split into top-level expressions, which start at lines without indentation.
+/// After an edit, only the top-level expressions around the edited text are lexed and
+/// built again, others are reused. The result is always the same as `parse` of the new code.
+///
+/// # Examples
+///
+/// ```
+/// # use cirru_parser::{parse, CirruDocument, TextEdit};
+/// let mut doc = CirruDocument::new("defn f (x)\n  + x 1\n\ndefn g (y)\n  * y 2\n");
+/// let tree = doc.apply_edit(&TextEdit::new(17..18, "2")).unwrap();
+/// assert_eq!(doc.code(), "defn f (x)\n  + x 2\n\ndefn g (y)\n  * y 2\n");
+/// assert_eq!(tree, parse(doc.code()).unwrap());
+/// assert_eq!(doc.last_reparsed(), 0..20);
+/// ```
+#[derive(Clone, Debug)]
+pub struct CirruDocument {
+  code: String,
+  chunks: Vec<ParsedChunk>,
+  reparsed: Range<usize>,
+}
+
+impl CirruDocument {
+  pub fn new(code: impl Into<String>) -> Self {
+    let code: String = code.into();
+    let chunks = parse_chunks(&code, 0, 1, |_| false);
+    let reparsed = 0..code.len();
+    Self { code, chunks, reparsed }
+  }
+
+  pub fn code(&self) -> &str {
+    &self.code
+  }
+
+  /// the same result as `parse(self.code())`
+  pub fn tree(&self) -> Result<Vec<Cirru>, CirruError> {
+    combine_chunks(&self.chunks)
+  }
+
+  /// range of current code that was parsed again by the last edit
+  pub fn last_reparsed(&self) -> Range<usize> {
+    self.reparsed.to_owned()
+  }
+
+  /// Applies an edit and returns the new tree. Fails without changing the document
+  /// when the range is out of the code or not at character boundaries.
+  pub fn apply_edit(&mut self, edit: &TextEdit) -> Result<Vec<Cirru>, CirruError> {
+    let Range { start, end } = edit.range;
+    if start > end || end > self.code.len() || !self.code.is_char_boundary(start) || !self.code.is_char_boundary(end) {
+      return Err(CirruError::from_message(format!(
+        "edit range {start}..{end} is not valid for code of {} bytes",
+        self.code.len()
+      )));
+    }
+
+    let removed_lines = self.code[start..end].matches('\n').count();
+    let added_lines = edit.text.matches('\n').count();
+    self.code.replace_range(start..end, &edit.text);
+    let shift = |x: usize| x + edit.text.len() - (end - start);
+    let shift_line = |x: usize| x + added_lines - removed_lines;
+
+    // chunks ending before the edit keep their text and their boundaries
+    let first = self.chunks.iter().position(|c| c.range.end >= start).unwrap_or(self.chunks.len());
+    let (from, line) = self.chunks.get(first).map_or((0, 1), |c| (c.range.start, c.line));
+    // chunks starting after the edit can be reused once parsing gets back to their start
+    let old_suffix = self.chunks.partition_point(|c| c.range.start <= end);
+    let mut reuse_from = self.chunks.len();
+    let old_chunks = &self.chunks;
+    let mut chunks: Vec<ParsedChunk> = parse_chunks(&self.code, from, line, |offset| {
+      let idx = old_suffix + old_chunks[old_suffix..].partition_point(|c| shift(c.range.start) < offset);
+      if old_chunks.get(idx).is_some_and(|c| shift(c.range.start) == offset) {
+        reuse_from = idx;
+        true
+      } else {
+        false
+      }
+    });
+    self.reparsed = from..chunks.last().map_or(from, |c| c.range.end);
+
+    let mut suffix: Vec<ParsedChunk> = self.chunks.drain(reuse_from.max(first)..).collect();
+    for chunk in &mut suffix {
+      chunk.range = shift(chunk.range.start)..shift(chunk.range.end);
+      chunk.line = shift_line(chunk.line);
+    }
+    self.chunks.truncate(first);
+    self.chunks.append(&mut chunks);
+    self.chunks.append(&mut suffix);
+
+    // error positions and snippets of reused chunks may have changed, errors are rare so parse again
+    for chunk in &mut self.chunks {
+      if chunk.result.is_err() && (chunk.range.end <= self.reparsed.start || chunk.range.start >= self.reparsed.end) {
+        let range_end = chunk.range.end;
+        *chunk = parse_chunks(&self.code, chunk.range.start, chunk.line, |offset| offset >= range_end).remove(0);
+      }
+    }
+
+    self.tree()
+  }
+}
@@ -0,0 +1,81 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use crate::primes::Cirru;
+
+/// Shares one `Arc<str>` among leaves of the same text, see `parse_with_interner`.
+///
+/// An interner can be kept for many files, so that common symbols like `defn` or `let`
+/// are stored once for a whole project. Leaves sharing an `Arc` are compared by pointer first.
+///
+/// # Examples
+///
+/// ```
+/// # use std::sync::Arc;
+/// # use cirru_parser::{Cirru, LeafInterner};
+/// let mut interner = LeafInterner::new();
+/// let a = interner.intern("defn");
+/// let b = interner.intern("defn");
+/// assert!(Arc::ptr_eq(&a, &b));
+/// assert_eq!(interner.len(), 1);
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct LeafInterner {
+  leaves: HashSet<Arc<str>>,
+}
+
+impl LeafInterner {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// returns the shared `Arc` of `text`, adding it when it is new
+  pub fn intern(&mut self, text: &str) -> Arc<str> {
+    match self.leaves.get(text) {
+      Some(s) => s.to_owned(),
+      None => {
+        let s: Arc<str> = text.into();
+        self.leaves.insert(s.to_owned());
+        s
+      }
+    }
+  }
+
+  /// rebuilds a tree with leaves shared through this interner
+  pub fn intern_tree(&mut self, tree: &Cirru) -> Cirru {
+    let xs = match tree {
+      Cirru::Leaf(s) => return Cirru::Leaf(self.intern(s)),
+      Cirru::List(xs) => xs,
+    };
+    // lists being copied, with the items left to copy and the items copied so far
+    let mut stack = vec![(xs.iter(), Vec::with_capacity(xs.len()))];
+    loop {
+      let (items, copied) = stack.last_mut().expect("stack has the outer list");
+      match items.next() {
+        Some(Cirru::Leaf(s)) => copied.push(Cirru::Leaf(self.intern(s))),
+        Some(Cirru::List(ys)) => stack.push((ys.iter(), Vec::with_capacity(ys.len()))),
+        None => {
+          let (_, copied) = stack.pop().expect("stack has the outer list");
+          match stack.last_mut() {
+            Some((_, parent)) => parent.push(Cirru::List(copied)),
+            None => return Cirru::List(copied),
+          }
+        }
+      }
+    }
+  }
+
+  /// number of distinct leaves
+  pub fn len(&self) -> usize {
+    self.leaves.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.leaves.is_empty()
+  }
+
+  /// drops leaves that no tree uses any more
+  pub fn shrink(&mut self) {
+    self.leaves.retain(|s| Arc::strong_count(s) > 1);
+  }
+}
@@ -0,0 +1,953 @@
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::ops::Range;
+use std::str::Chars;
+
+use crate::error::{CirruError, CirruErrorKind, ErrorContext, SourcePos, Span};
+use crate::options::{MixedIndent, ParseLimits, ParseOptions, WhitespacePolicy};
+use crate::primes::{CirruLexItem, CirruLexItemList, CirruLexState};
+
+const DEFAULT_BUFFER_CAPACITY: usize = 8;
+/// byte order mark, which some editors put at the start of UTF-8 files
+const BOM: char = '\u{feff}';
+/// bytes of code on each side of an error in its snippet
+pub(crate) const SNIPPET_WINDOW: usize = 20;
+
+/// Table of bytes that may end a run, other bytes are skipped in bulk
+const fn stop_bytes(ascii: &[u8], non_ascii: bool) -> [bool; 256] {
+  let mut table = [non_ascii; 256];
+  let mut idx = 0;
+  while idx < 128 {
+    table[idx] = false;
+    idx += 1;
+  }
+  idx = 0;
+  while idx < ascii.len() {
+    table[ascii[idx] as usize] = true;
+    idx += 1;
+  }
+  table
+}
+
+/// bytes that may end a token, non-ASCII characters are checked one by one since they may be whitespace
+const TOKEN_STOP: [bool; 256] = stop_bytes(b" \n()\"\t\r\x0b\x0c", true);
+/// bytes that may end plain text in a string
+const STRING_STOP: [bool; 256] = stop_bytes(b"\"\\\n", false);
+
+/// Position tracker for lexical analysis
+struct LexerContext {
+  line: usize,
+  column: usize,
+  offset: usize,
+}
+
+impl LexerContext {
+  fn new() -> Self {
+    Self::at_line_start(1, 0)
+  }
+
+  fn at_line_start(line: usize, offset: usize) -> Self {
+    Self { line, column: 1, offset }
+  }
+
+  fn current_pos(&self) -> SourcePos {
+    SourcePos::new(self.line, self.column, self.offset)
+  }
+
+  fn advance(&mut self, c: char) {
+    self.offset += c.len_utf8();
+    if c == '\n' {
+      self.line += 1;
+      self.column = 1;
+    } else {
+      self.column += 1;
+    }
+  }
+
+  /// span of a single character at the current position, which is not a newline
+  fn char_span(&self, c: char) -> Span {
+    let end = SourcePos::new(self.line, self.column + 1, self.offset + c.len_utf8());
+    Span::new(self.current_pos(), end)
+  }
+}
+
+fn get_context_snippet(code: &str, offset: usize, window: usize) -> String {
+  let mut start = offset.saturating_sub(window);
+  while !code.is_char_boundary(start) {
+    start -= 1;
+  }
+  let mut end = (offset + window).min(code.len());
+  while !code.is_char_boundary(end) {
+    end += 1;
+  }
+  let snippet = &code[start..end];
+  // Use escape_debug to show special characters like \n, \t, spaces clearly
+  let escaped: String = snippet.chars().take(60).flat_map(|c| c.escape_debug()).collect();
+  format!("...{escaped}...")
+}
+
+/// Token with text borrowed from the code, only strings with escapes own their text
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum LexToken<'a> {
+  Open,
+  Close,
+  Indent(usize),
+  Str(Cow<'a, str>),
+}
+
+impl From<LexToken<'_>> for CirruLexItem {
+  fn from(token: LexToken<'_>) -> Self {
+    match token {
+      LexToken::Open => CirruLexItem::Open,
+      LexToken::Close => CirruLexItem::Close,
+      LexToken::Indent(n) => CirruLexItem::Indent(n),
+      LexToken::Str(s) => CirruLexItem::Str(s.into_owned()),
+    }
+  }
+}
+
+/// Pull-based lexer for Cirru syntax, yielding tokens together with their source ranges.
+/// It uses a state machine to handle different parts of the syntax, such as strings,
+/// tokens, and indentation, and only scans as far as the tokens being pulled.
+///
+/// A lexer created with `new` stops after the first error. One created with `recovering`
+/// yields errors as `Err` items and goes on with a repaired state.
+///
+/// # Examples
+///
+/// ```
+/// # use cirru_parser::{CirruLexItem, Lexer};
+/// let mut lexer = Lexer::new("a (b)\nc");
+/// let (item, span) = lexer.nth(1).unwrap().unwrap();
+/// assert_eq!(item, "a".into());
+/// assert_eq!(span.start.column, 1);
+/// ```
+pub struct Lexer<'a> {
+  code: &'a str,
+  chars: Chars<'a>,
+  state: CirruLexState,
+  /// text of current string literal once it has an escape, other tokens are sliced from the code
+  buffer: String,
+  escaped: bool,
+  /// current string literal is a raw string, which starts with `r"` and has no escapes
+  raw: bool,
+  /// start of a block string marker whose first quote was just scanned
+  block_start: Option<SourcePos>,
+  /// hex digits of current `\u{..}` escape, `None` before the `{`
+  unicode_digits: Option<String>,
+  /// start of current line, where its indentation starts
+  indent_start: SourcePos,
+  /// columns of indentation at the start of current line, tabs may take more than one
+  indent_width: usize,
+  /// first character of the indentation that does not match the indent unit
+  indent_mixed: Option<(char, SourcePos)>,
+  options: ParseOptions,
+  ctx: LexerContext,
+  /// where the current token or string literal started
+  token_start: SourcePos,
+  /// items produced by the last character, a character may end a token and open a list
+  pending: VecDeque<Result<(LexToken<'a>, Span), CirruError>>,
+  /// closers expected for lists opened by parentheses and brackets, only tracked with a reader table
+  delimiters: Vec<char>,
+  /// for each form started by a reader prefix, the number of open delimiters when it started
+  prefixed: Vec<usize>,
+  recovering: bool,
+  done: bool,
+  /// tokens emitted so far, for `ParseLimits::max_tokens`
+  emitted: usize,
+  /// a limit was hit, nothing more is emitted
+  limited: bool,
+  /// the size of code is checked once, before the first token
+  size_checked: bool,
+}
+
+impl<'a> Lexer<'a> {
+  pub fn new(code: &'a str) -> Self {
+    let ctx = LexerContext::new();
+    Self {
+      code,
+      chars: code.chars(),
+      state: CirruLexState::Indent,
+      buffer: String::with_capacity(DEFAULT_BUFFER_CAPACITY),
+      escaped: false,
+      raw: false,
+      block_start: None,
+      unicode_digits: None,
+      indent_start: ctx.current_pos(),
+      indent_width: 0,
+      indent_mixed: None,
+      options: ParseOptions::default(),
+      token_start: ctx.current_pos(),
+      ctx,
+      pending: VecDeque::with_capacity(2),
+      delimiters: vec![],
+      prefixed: vec![],
+      recovering: false,
+      done: false,
+      emitted: 0,
+      limited: false,
+      size_checked: false,
+    }
+  }
+
+  /// Lexes `code[range]`, where `range` starts at the beginning of `line`.
+  /// Positions and error snippets are relative to the whole `code`.
+  pub(crate) fn for_range(code: &'a str, range: Range<usize>, line: usize) -> Self {
+    let ctx = LexerContext::at_line_start(line, range.start);
+    Self {
+      chars: code[range].chars(),
+      token_start: ctx.current_pos(),
+      indent_start: ctx.current_pos(),
+      ctx,
+      ..Self::new(code)
+    }
+  }
+
+  /// A lexer that yields errors as items and keeps going: odd indentation is rounded down,
+  /// unclosed strings end at line end, invalid escapes are kept as they are,
+  /// and `)` at line start is dropped.
+  pub fn recovering(code: &'a str) -> Self {
+    Self {
+      recovering: true,
+      ..Self::new(code)
+    }
+  }
+
+  /// Rejects lines indented deeper than `levels` with `CirruErrorKind::IndentationTooDeep`.
+  /// When recovering, such lines are taken as indented by `levels`.
+  pub fn with_max_indent(mut self, levels: usize) -> Self {
+    self.options.max_indent = Some(levels);
+    self
+  }
+
+  /// Lexes with the indent unit, handling of mixed indentation, limits and reader table in `options`
+  pub fn with_options(mut self, options: &ParseOptions) -> Self {
+    self.options = options.to_owned();
+    self
+  }
+
+  /// Stops with an error when the code, a leaf or the number of tokens goes over `limits`,
+  /// also when recovering. Nesting and top-level expressions are checked when building the tree.
+  pub fn with_limits(mut self, limits: ParseLimits) -> Self {
+    self.options.limits = limits;
+    self
+  }
+
+  pub(crate) fn options(&self) -> &ParseOptions {
+    &self.options
+  }
+
+  /// yields only an error when the code is larger than the limit, at the first byte past it
+  fn check_input_size(&mut self) {
+    if let Some(max) = self.options.limits.max_input_size
+      && self.code.len() > max
+    {
+      let mut end = max;
+      while !self.code.is_char_boundary(end) {
+        end -= 1;
+      }
+      let before = &self.code[..end];
+      let line = before.bytes().filter(|b| *b == b'\n').count() + 1;
+      let column = before.rsplit('\n').next().map_or(0, |s| s.chars().count()) + 1;
+      let size = self.code.len();
+      self.stop_at_limit(SourcePos::new(line, column, end), CirruErrorKind::InputTooLarge { size, max });
+    }
+  }
+
+  /// ends lexing with the error of a limit, tokens emitted before are still yielded
+  fn stop_at_limit(&mut self, pos: SourcePos, kind: CirruErrorKind) {
+    let error = self.error_at(pos, kind, "checking limits");
+    self.pending.push_back(Err(error));
+    self.limited = true;
+    self.done = true;
+  }
+
+  /// position of the next character to scan
+  pub fn current_pos(&self) -> SourcePos {
+    self.ctx.current_pos()
+  }
+
+  /// whether the last character scanned is inside a string literal
+  pub(crate) fn in_string(&self) -> bool {
+    matches!(
+      self.state,
+      CirruLexState::Str | CirruLexState::Escape | CirruLexState::UnicodeEscape
+    )
+  }
+
+  fn emit(&mut self, item: LexToken<'a>, span: Span) {
+    if self.limited {
+      return;
+    }
+    let limits = self.options.limits;
+    if let (LexToken::Str(s), Some(max)) = (&item, limits.max_leaf_len)
+      && s.len() > max
+    {
+      self.stop_at_limit(span.start, CirruErrorKind::LeafTooLong { len: s.len(), max });
+      return;
+    }
+    if let Some(max) = limits.max_tokens
+      && self.emitted >= max
+    {
+      self.stop_at_limit(span.start, CirruErrorKind::TooManyTokens { max });
+      return;
+    }
+    self.emitted += 1;
+    self.pending.push_back(Ok((item, span)));
+  }
+
+  /// emits current token, which ends at current position
+  fn emit_token(&mut self) {
+    let token = &self.code[self.token_start.offset..self.ctx.offset];
+    self.emit(
+      LexToken::Str(Cow::Borrowed(token)),
+      Span::new(self.token_start, self.ctx.current_pos()),
+    );
+    self.finish_prefixed();
+  }
+
+  fn start_string(&mut self) {
+    self.state = CirruLexState::Str;
+    self.token_start = self.ctx.current_pos();
+    self.escaped = false;
+    self.raw = false;
+  }
+
+  /// starts a raw string at the quote after the `r` token, the string starts at the `r`
+  fn start_raw_string(&mut self) {
+    self.state = CirruLexState::Str;
+    self.escaped = false;
+    self.raw = true;
+  }
+
+  /// text of current string literal so far, without the opening quote
+  fn string_text(&mut self) -> Cow<'a, str> {
+    if self.escaped {
+      Cow::Owned(std::mem::replace(&mut self.buffer, String::with_capacity(DEFAULT_BUFFER_CAPACITY)))
+    } else {
+      let opening = if self.raw { 2 } else { 1 };
+      Cow::Borrowed(&self.code[self.token_start.offset + opening..self.ctx.offset])
+    }
+  }
+
+  /// Reads a block string after the first quote of its `"""` marker, which ends its line.
+  /// The text is on the lines after it that are indented by one more level, with that indentation removed,
+  /// and ends before the first line with content indented less. Blank lines are empty lines of the text,
+  /// and there are no escapes.
+  fn read_block_string(&mut self, start: SourcePos) -> Result<(), CirruError> {
+    for _ in 0..2 {
+      if let Some(c) = self.chars.next() {
+        self.ctx.advance(c);
+      }
+    }
+    let rest: &'a str = self.chars.as_str();
+    let marker_line_end = rest.find('\n').unwrap_or(rest.len());
+    if let Some((idx, c)) = rest[..marker_line_end]
+      .char_indices()
+      .find(|(_, c)| *c != ' ' && !self.options.whitespace.takes(*c))
+    {
+      let column = self.ctx.column + rest[..idx].chars().count();
+      let pos = SourcePos::new(self.ctx.line, column, self.ctx.offset + idx);
+      let error = self.error_at(pos, CirruErrorKind::UnexpectedChar(c), "after block string marker");
+      self.report(error)?;
+      // recover by dropping the rest of the line
+    }
+
+    let level = self.indent_width / self.options.unit_width();
+    let prefix = self.options.indent_text(level + 1);
+    let mut lines: Vec<&str> = vec![];
+    let mut blank_lines = 0;
+    // end of the last line in the block
+    let mut end = marker_line_end;
+    let mut line_start = marker_line_end + 1;
+    while line_start <= rest.len() {
+      let line_end = rest[line_start..].find('\n').map_or(rest.len(), |idx| line_start + idx);
+      let mut line = &rest[line_start..line_end];
+      if self.options.whitespace != WhitespacePolicy::Reject {
+        line = line.strip_suffix('\r').unwrap_or(line);
+      }
+      if line.trim().is_empty() {
+        blank_lines += 1;
+      } else if let Some(text) = line.strip_prefix(prefix.as_str()) {
+        lines.extend(std::iter::repeat_n("", blank_lines));
+        blank_lines = 0;
+        lines.push(text);
+        end = line_end;
+      } else {
+        break;
+      }
+      line_start = line_end + 1;
+    }
+
+    for c in rest[..end].chars() {
+      self.ctx.advance(c);
+    }
+    self.chars = rest[end..].chars();
+    self.emit(
+      LexToken::Str(Cow::Owned(lines.join("\n"))),
+      Span::new(start, self.ctx.current_pos()),
+    );
+    self.finish_prefixed();
+    Ok(())
+  }
+
+  /// emits current string literal, which ends at `end`
+  fn emit_string(&mut self, end: SourcePos) {
+    let text = self.string_text();
+    self.emit(LexToken::Str(text), Span::new(self.token_start, end));
+    self.finish_prefixed();
+  }
+
+  /// closes the lists of reader prefixes whose form has just ended
+  fn finish_prefixed(&mut self) {
+    while self.prefixed.last().is_some_and(|depth| *depth >= self.delimiters.len()) {
+      self.prefixed.pop();
+      let pos = self.ctx.current_pos();
+      self.emit(LexToken::Close, Span::new(pos, pos));
+    }
+  }
+
+  /// Takes parentheses, brackets and prefixes when there is a reader table,
+  /// returns `false` for other characters. Brackets become a list with the head leaf,
+  /// which has an empty span at the bracket, and prefixes wrap the next form.
+  fn step_reader(&mut self, c: char) -> Result<bool, CirruError> {
+    let reader = self.options.reader;
+    let whitespace = self.options.whitespace;
+    let opening = if c == '(' {
+      Some(None)
+    } else {
+      reader.bracket_by_open(c).map(Some)
+    };
+    if let Some(bracket) = opening {
+      match self.state {
+        CirruLexState::Token => self.emit_token(),
+        CirruLexState::Indent => self.emit_indentation()?,
+        _ => (),
+      }
+      let span = self.ctx.char_span(c);
+      self.emit(LexToken::Open, span);
+      match bracket {
+        Some(bracket) => {
+          self.emit(LexToken::Str(Cow::Borrowed(bracket.head)), Span::new(span.start, span.start));
+          self.delimiters.push(bracket.close);
+        }
+        None => self.delimiters.push(')'),
+      }
+      self.state = CirruLexState::Space;
+      return Ok(true);
+    }
+    if c == ')' || reader.bracket_by_close(c).is_some() {
+      match self.state {
+        CirruLexState::Token => self.emit_token(),
+        CirruLexState::Indent => {
+          let error = self.error_here(CirruErrorKind::UnexpectedCloseParen, "at line start");
+          self.report(error)?;
+          // recover by dropping the parenthesis, the line still starts here
+          self.emit_indentation()?;
+          self.state = CirruLexState::Space;
+          return Ok(true);
+        }
+        _ => (),
+      }
+      if let Some(expected) = self.delimiters.pop()
+        && expected != c
+      {
+        let error = self.error_here(CirruErrorKind::UnmatchedParen, &format!("expected `{expected}`"));
+        self.report(error)?;
+        // recover by closing the list anyway
+      }
+      self.emit(LexToken::Close, self.ctx.char_span(c));
+      self.state = CirruLexState::Space;
+      self.finish_prefixed();
+      return Ok(true);
+    }
+    if let Some(prefix) = reader.prefix(c)
+      && self.state != CirruLexState::Token
+      && self
+        .chars
+        .clone()
+        .next()
+        .is_some_and(|next| !matches!(next, ' ' | '\n' | ')') && !whitespace.takes(next) && reader.bracket_by_close(next).is_none())
+    {
+      if self.state == CirruLexState::Indent {
+        self.emit_indentation()?;
+      }
+      let span = self.ctx.char_span(c);
+      self.emit(LexToken::Open, span);
+      self.emit(LexToken::Str(Cow::Borrowed(prefix.head)), Span::new(span.start, span.start));
+      self.prefixed.push(self.delimiters.len());
+      self.state = CirruLexState::Space;
+      return Ok(true);
+    }
+    Ok(false)
+  }
+
+  /// called at a newline, the next line starts after it
+  fn start_indentation(&mut self) {
+    self.state = CirruLexState::Indent;
+    self.indent_start = SourcePos::new(self.ctx.line + 1, 1, self.ctx.offset + 1);
+    self.indent_width = 0;
+    self.indent_mixed = None;
+  }
+
+  /// takes a space or tab at line start, mismatches are only reported for lines with content
+  fn push_indent_char(&mut self, c: char) {
+    if c != self.options.indent_char() && self.options.mixed_indent == MixedIndent::Reject && self.indent_mixed.is_none() {
+      self.indent_mixed = Some((c, self.ctx.current_pos()));
+    }
+    self.indent_width = if c == '\t' {
+      let tab_width = self.options.tab_width();
+      (self.indent_width / tab_width + 1) * tab_width
+    } else {
+      self.indent_width + 1
+    };
+  }
+
+  /// When recovering, yields the error so the caller can repair the state and go on,
+  /// otherwise returns the error to stop lexing.
+  fn report(&mut self, error: CirruError) -> Result<(), CirruError> {
+    if self.recovering {
+      self.pending.push_back(Err(error));
+      Ok(())
+    } else {
+      Err(error)
+    }
+  }
+
+  fn error_here(&self, kind: CirruErrorKind, context_info: &str) -> CirruError {
+    self.error_at(self.ctx.current_pos(), kind, context_info)
+  }
+
+  fn error_at(&self, pos: SourcePos, kind: CirruErrorKind, context_info: &str) -> CirruError {
+    let snippet = get_context_snippet(self.code, pos.offset, SNIPPET_WINDOW);
+    CirruError::with_context(kind, ErrorContext::new(pos, Some(snippet), context_info.to_string()))
+  }
+
+  /// emits the indentation of current line
+  fn emit_indentation(&mut self) -> Result<(), CirruError> {
+    if let Some((c, pos)) = self.indent_mixed.take() {
+      let info = match c {
+        '\t' => "tab in indentation by spaces",
+        _ => "space in indentation by tabs",
+      };
+      let error = self.error_at(pos, CirruErrorKind::UnexpectedIndentChar(c), info);
+      self.report(error)?;
+      // recover by taking a tab as one level, and a space as one column
+    }
+    let width = self.indent_width;
+    let unit = self.options.unit_width();
+    if !width.is_multiple_of(unit) {
+      let error = self.error_here(CirruErrorKind::InvalidIndentation(width), "checking indentation");
+      self.report(error)?;
+      // recover by rounding down to the outer level
+    }
+    let mut depth = width / unit;
+    if let Some(max) = self.options.max_indent
+      && depth > max
+    {
+      let error = self.error_here(CirruErrorKind::IndentationTooDeep { depth, max }, "checking indentation");
+      self.report(error)?;
+      depth = max;
+    }
+    self.emit(LexToken::Indent(depth), Span::new(self.indent_start, self.ctx.current_pos()));
+    Ok(())
+  }
+
+  /// Takes whitespace other than spaces, newlines, and tabs in indentation, outside strings.
+  /// `\r` before a newline is skipped at line start, other whitespace works like a space.
+  fn step_whitespace(&mut self, c: char) -> Result<(), CirruError> {
+    if self.options.whitespace == WhitespacePolicy::Reject {
+      let error = self.error_here(CirruErrorKind::UnexpectedWhitespace(c), "whitespace other than space and newline");
+      self.report(error)?;
+      // recover as normalized
+    }
+    if c == '\r' && self.state == CirruLexState::Indent {
+      Ok(())
+    } else {
+      self.step(' ')
+    }
+  }
+
+  /// skips a byte order mark at the start of code, the first line still starts at column 1
+  fn skip_bom(&mut self) -> Result<(), CirruError> {
+    if self.options.whitespace == WhitespacePolicy::Reject {
+      let error = self.error_here(CirruErrorKind::UnexpectedChar(BOM), "byte order mark");
+      self.report(error)?;
+    }
+    self.ctx.offset += BOM.len_utf8();
+    self.indent_start = self.ctx.current_pos();
+    self.token_start = self.ctx.current_pos();
+    Ok(())
+  }
+
+  fn step(&mut self, c: char) -> Result<(), CirruError> {
+    // a `\r` not followed by newline stays in its token
+    if c != ' ' && c != '\n' && self.options.whitespace.takes(c) && (c != '\r' || self.chars.clone().next() == Some('\n')) {
+      match self.state {
+        CirruLexState::Space | CirruLexState::Token => return self.step_whitespace(c),
+        CirruLexState::Indent if c != '\t' => return self.step_whitespace(c),
+        _ => (),
+      }
+    }
+    if c == '"'
+      && self.options.block_strings
+      && matches!(self.state, CirruLexState::Space | CirruLexState::Indent)
+      && self.chars.as_str().starts_with("\"\"")
+    {
+      if self.state == CirruLexState::Indent {
+        self.emit_indentation()?;
+      }
+      // the rest is read after this quote is scanned
+      self.block_start = Some(self.ctx.current_pos());
+      self.state = CirruLexState::Space;
+      return Ok(());
+    }
+    if !self.options.reader.is_empty()
+      && matches!(self.state, CirruLexState::Space | CirruLexState::Token | CirruLexState::Indent)
+      && self.step_reader(c)?
+    {
+      return Ok(());
+    }
+    match self.state {
+      CirruLexState::Space => match c {
+        ' ' => {
+          self.state = CirruLexState::Space;
+        }
+        '\n' => {
+          self.start_indentation();
+        }
+        '(' => {
+          self.emit(LexToken::Open, self.ctx.char_span(c));
+          self.state = CirruLexState::Space;
+        }
+        ')' => {
+          self.emit(LexToken::Close, self.ctx.char_span(c));
+          self.state = CirruLexState::Space;
+        }
+        '"' => {
+          self.start_string();
+        }
+        _ => {
+          self.state = CirruLexState::Token;
+          self.token_start = self.ctx.current_pos();
+        }
+      },
+      CirruLexState::Token => match c {
+        ' ' => {
+          self.emit_token();
+          self.state = CirruLexState::Space;
+        }
+        '"' if self.options.raw_strings && &self.code[self.token_start.offset..self.ctx.offset] == "r" => {
+          self.start_raw_string();
+        }
+        '"' => {
+          self.emit_token();
+          self.start_string();
+        }
+        '\n' => {
+          self.emit_token();
+          self.start_indentation();
+        }
+        '(' => {
+          self.emit_token();
+          self.emit(LexToken::Open, self.ctx.char_span(c));
+          self.state = CirruLexState::Space;
+        }
+        ')' => {
+          self.emit_token();
+          self.emit(LexToken::Close, self.ctx.char_span(c));
+          self.state = CirruLexState::Space;
+        }
+        _ => {
+          self.state = CirruLexState::Token;
+        }
+      },
+      CirruLexState::Str => match c {
+        '"' => {
+          self.emit_string(self.ctx.char_span(c).end);
+          self.state = CirruLexState::Space;
+        }
+        '\\' if !self.raw => {
+          if !self.escaped {
+            // text is copied from here on, since escapes change it
+            self.buffer.clear();
+            self.buffer.push_str(&self.code[self.token_start.offset + 1..self.ctx.offset]);
+            self.escaped = true;
+          }
+          self.state = CirruLexState::Escape;
+        }
+        '\n' => {
+          let error = self.error_here(CirruErrorKind::UnexpectedNewlineInString, "in string literal");
+          self.report(error)?;
+          // recover by closing the string at line end
+          self.emit_string(self.ctx.current_pos());
+          self.start_indentation();
+        }
+        _ => {
+          self.state = CirruLexState::Str;
+          if self.escaped {
+            self.buffer.push(c);
+          }
+        }
+      },
+      CirruLexState::Escape => match c {
+        '"' => {
+          self.state = CirruLexState::Str;
+          self.buffer.push('"');
+        }
+        '\'' => {
+          self.state = CirruLexState::Str;
+          self.buffer.push('\'');
+        }
+        't' => {
+          self.state = CirruLexState::Str;
+          self.buffer.push('\t');
+        }
+        'n' => {
+          self.state = CirruLexState::Str;
+          self.buffer.push('\n');
+        }
+        'r' => {
+          self.state = CirruLexState::Str;
+          self.buffer.push('\r');
+        }
+        'u' => {
+          self.state = CirruLexState::UnicodeEscape;
+          self.unicode_digits = None;
+        }
+        '\\' => {
+          self.state = CirruLexState::Str;
+          self.buffer.push('\\');
+        }
+        _ => {
+          let error = self.error_here(CirruErrorKind::InvalidEscape(c), "invalid escape sequence in string");
+          self.report(error)?;
+          // recover by keeping the character as it is
+          self.state = CirruLexState::Str;
+          self.buffer.push(c);
+        }
+      },
+      CirruLexState::UnicodeEscape => match (&mut self.unicode_digits, c) {
+        (None, '{') => {
+          self.unicode_digits = Some(String::with_capacity(6));
+        }
+        (None, _) => return self.invalid_unicode_escape(c, "expected `{` after `\\u`"),
+        (Some(digits), '}') => {
+          let code = u32::from_str_radix(digits, 16).ok().and_then(char::from_u32);
+          match code {
+            Some(code) => {
+              self.buffer.push(code);
+              self.state = CirruLexState::Str;
+            }
+            None if digits.is_empty() => return self.invalid_unicode_escape(c, "expected hex digits in `\\u{}`"),
+            None => return self.invalid_unicode_escape(c, "not a unicode scalar value"),
+          }
+        }
+        (Some(digits), _) if c.is_ascii_hexdigit() => {
+          if digits.len() >= 6 {
+            return self.invalid_unicode_escape(c, "more than 6 hex digits");
+          }
+          digits.push(c);
+        }
+        (Some(_), _) => return self.invalid_unicode_escape(c, "expected hex digit or `}`"),
+      },
+      CirruLexState::Indent => match c {
+        ' ' | '\t' => {
+          self.state = CirruLexState::Indent;
+          self.push_indent_char(c);
+        }
+        '\n' => {
+          self.start_indentation();
+        }
+        '"' => {
+          self.emit_indentation()?;
+          self.start_string();
+        }
+        '(' => {
+          self.emit_indentation()?;
+          self.emit(LexToken::Open, self.ctx.char_span(c));
+          self.state = CirruLexState::Space;
+        }
+        ')' => {
+          let error = self.error_here(CirruErrorKind::UnexpectedCloseParen, "at line start");
+          self.report(error)?;
+          // recover by dropping the parenthesis, the line still starts here
+          self.emit_indentation()?;
+          self.state = CirruLexState::Space;
+        }
+        _ => {
+          self.emit_indentation()?;
+          self.state = CirruLexState::Token;
+          self.token_start = self.ctx.current_pos();
+        }
+      },
+    }
+    Ok(())
+  }
+
+  /// text of current `\u{..}` escape read so far
+  fn unicode_escape_text(&self) -> String {
+    match &self.unicode_digits {
+      Some(digits) => format!("\\u{{{digits}"),
+      None => String::from("\\u"),
+    }
+  }
+
+  /// Reports a malformed `\u{..}` escape at character `c`. Recovers by keeping the escape
+  /// text as it is, and `c` is scanned again as part of the string unless it closes the escape.
+  fn invalid_unicode_escape(&mut self, c: char, context_info: &str) -> Result<(), CirruError> {
+    let text = self.unicode_escape_text();
+    let error = self.error_here(CirruErrorKind::InvalidUnicodeEscape(text.to_owned()), context_info);
+    self.report(error)?;
+    self.buffer.push_str(&text);
+    self.state = CirruLexState::Str;
+    if c == '}' {
+      self.buffer.push(c);
+      Ok(())
+    } else {
+      self.step(c)
+    }
+  }
+
+  /// handles the state left at end of file
+  fn finish(&mut self) -> Result<(), CirruError> {
+    match self.state {
+      CirruLexState::Space => Ok(()),
+      CirruLexState::Token => {
+        self.emit_token();
+        Ok(())
+      }
+      CirruLexState::Escape => {
+        let pos = self.ctx.current_pos();
+        let error_ctx = ErrorContext::new(pos, None, "at end of file".to_string());
+        self.report(CirruError::with_context(
+          CirruErrorKind::Other("incomplete escape sequence".to_string()),
+          error_ctx,
+        ))?;
+        self.emit_string(pos);
+        Ok(())
+      }
+      CirruLexState::UnicodeEscape => {
+        let pos = self.ctx.current_pos();
+        let text = self.unicode_escape_text();
+        let error_ctx = ErrorContext::new(pos, None, "at end of file".to_string());
+        self.report(CirruError::with_context(
+          CirruErrorKind::InvalidUnicodeEscape(text.to_owned()),
+          error_ctx,
+        ))?;
+        self.buffer.push_str(&text);
+        self.emit_string(pos);
+        Ok(())
+      }
+      CirruLexState::Indent => Ok(()),
+      CirruLexState::Str => {
+        let pos = self.ctx.current_pos();
+        let error_ctx = ErrorContext::new(pos, None, "unclosed string literal".to_string());
+        self.report(CirruError::with_context(CirruErrorKind::UnexpectedEof, error_ctx))?;
+        self.emit_string(pos);
+        Ok(())
+      }
+    }
+  }
+
+  /// Scans a run of characters that keep the state as it is at once, instead of stepping them one by one:
+  /// spaces between tokens or in indentation, the rest of a token, and plain text in a string.
+  /// Characters that end a run are all ASCII, so runs end at character boundaries.
+  fn skip_run(&mut self) {
+    let rest: &'a str = self.chars.as_str();
+    let bytes = rest.as_bytes();
+    let len = match self.state {
+      CirruLexState::Space => bytes.iter().position(|b| *b != b' ').unwrap_or(bytes.len()),
+      CirruLexState::Indent if self.options.indent_char() == ' ' => {
+        let len = bytes.iter().position(|b| *b != b' ').unwrap_or(bytes.len());
+        self.indent_width += len;
+        len
+      }
+      CirruLexState::Token if self.options.reader.brackets.is_empty() => {
+        bytes.iter().position(|b| TOKEN_STOP[*b as usize]).unwrap_or(bytes.len())
+      }
+      CirruLexState::Str => bytes.iter().position(|b| STRING_STOP[*b as usize]).unwrap_or(bytes.len()),
+      _ => 0,
+    };
+    if len == 0 {
+      return;
+    }
+    let run = &rest[..len];
+    if self.state == CirruLexState::Str {
+      if self.escaped {
+        self.buffer.push_str(run);
+      }
+      // continuation bytes of UTF-8 do not start a column
+      self.ctx.column += run.bytes().filter(|b| b & 0xc0 != 0x80).count();
+    } else {
+      self.ctx.column += len;
+    }
+    self.ctx.offset += len;
+    self.chars = rest[len..].chars();
+  }
+
+  /// next token with text borrowed from the code when it has no escapes
+  pub(crate) fn next_token(&mut self) -> Option<Result<(LexToken<'a>, Span), CirruError>> {
+    if !self.size_checked {
+      self.size_checked = true;
+      self.check_input_size();
+    }
+    loop {
+      if let Some(item) = self.pending.pop_front() {
+        return Some(item);
+      }
+      if self.done {
+        return None;
+      }
+      self.skip_run();
+      let result = match self.chars.next() {
+        Some(BOM) if self.ctx.offset == 0 => self.skip_bom(),
+        Some(c) => {
+          let result = self.step(c);
+          self.ctx.advance(c);
+          match self.block_start.take() {
+            Some(start) if result.is_ok() => self.read_block_string(start),
+            _ => result,
+          }
+        }
+        None => {
+          self.done = true;
+          self.finish()
+        }
+      };
+      // errors after a limit was hit are left out, since lexing stopped there
+      if let Err(error) = result
+        && !self.limited
+      {
+        // tokens before the error are still yielded, then lexing stops
+        self.done = true;
+        self.pending.push_back(Err(error));
+      }
+    }
+  }
+}
+
+impl Iterator for Lexer<'_> {
+  type Item = Result<(CirruLexItem, Span), CirruError>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    self.next_token().map(|item| item.map(|(token, span)| (token.into(), span)))
+  }
+}
+
+/// The lexer for Cirru syntax. It scans the code and returns a flat list of tokens.
+/// Use `Lexer` to pull tokens lazily along with their positions.
+pub fn lex(initial_code: &str) -> Result<CirruLexItemList, CirruError> {
+  lex_with_limits(initial_code, &ParseLimits::default())
+}
+
+/// Same as `lex`, failing when the code, a leaf or the number of tokens goes over `limits`,
+/// see `Lexer::with_limits`.
+pub fn lex_with_limits(initial_code: &str, limits: &ParseLimits) -> Result<CirruLexItemList, CirruError> {
+  // guessed an initial length
+  let mut acc: CirruLexItemList = Vec::with_capacity(initial_code.len() >> 4);
+  for item in Lexer::new(initial_code).with_limits(*limits) {
+    acc.push(item?.0);
+  }
+  Ok(acc)
+}
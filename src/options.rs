@@ -0,0 +1,302 @@
+use std::sync::Arc;
+
+use crate::MAX_NESTING_DEPTH;
+use crate::sugar::{CommaSugar, DollarSugar, SugarResolver};
+
+/// Indentation of one level
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum IndentUnit {
+  #[default]
+  TwoSpaces,
+  FourSpaces,
+  Tab,
+}
+
+/// How to treat tabs in indentation by spaces, or spaces in indentation by tabs
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MixedIndent {
+  /// fails with `CirruErrorKind::UnexpectedIndentChar`
+  #[default]
+  Reject,
+  /// A tab moves to the next column that is a multiple of `tab_width`, a space moves by one column,
+  /// then columns are divided by the width of the unit. With `IndentUnit::Tab`, that width is `tab_width`.
+  Normalize { tab_width: usize },
+}
+
+/// How to treat `\r\n` line endings, a byte order mark at the start of code,
+/// and whitespace other than spaces and newlines outside strings.
+/// A `\r` that is not followed by a newline is part of its token.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum WhitespacePolicy {
+  /// `\r` before a newline is skipped, a leading byte order mark is skipped,
+  /// and tabs between tokens separate them like a space. Other whitespace,
+  /// like `\u{a0}` or `\u{3000}`, is part of leaves, like `parse` always did.
+  #[default]
+  Normalize,
+  /// like `Normalize`, and any other whitespace separates tokens like a space,
+  /// or counts as a space in indentation
+  NormalizeAll,
+  /// fails with `CirruErrorKind::UnexpectedWhitespace` at the first such character,
+  /// or `CirruErrorKind::UnexpectedChar` for a byte order mark
+  Reject,
+}
+
+impl WhitespacePolicy {
+  /// whether `c`, whitespace other than space and newline, is handled by the policy instead of being part of a leaf
+  pub(crate) fn takes(self, c: char) -> bool {
+    match self {
+      WhitespacePolicy::Normalize => c == '\t' || c == '\r',
+      WhitespacePolicy::NormalizeAll | WhitespacePolicy::Reject => c.is_whitespace(),
+    }
+  }
+}
+
+/// What to do with comments, which are expressions headed by `;` or `;;`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CommentPolicy {
+  /// kept in the tree as expressions, like `parse` does
+  #[default]
+  Keep,
+  /// removed at all levels, after the resolvers have run
+  Strip,
+}
+
+/// Brackets read into a list with `head` in front, like `[a b]` into `([] a b)`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ReaderBracket {
+  pub open: char,
+  pub close: char,
+  pub head: &'static str,
+}
+
+/// A prefix read into a list of `head` and the form after it, like `'x` into `(quote x)`.
+/// The prefix must be followed directly by a leaf, a string, a list or another prefix,
+/// otherwise it is a character of a leaf.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ReaderPrefix {
+  pub prefix: char,
+  pub head: &'static str,
+}
+
+/// Reader macros of a dialect, used by the lexer with `ParseOptions::reader`
+/// and by the writer with `CirruWriterOptions::reader`. Empty by default.
+///
+/// Bracket characters end leaves like parentheses do, and prefixes only apply at the start of leaves.
+///
+/// # Examples
+///
+/// ```
+/// # use cirru_parser::{parse, parse_with_options, ParseOptions, ReaderTable};
+/// let options = ParseOptions {
+///   reader: ReaderTable::STANDARD,
+///   ..Default::default()
+/// };
+/// let tree = parse_with_options("def v [1 2] {a 'b}", &options).unwrap();
+/// assert_eq!(tree, parse("def v ([] 1 2) ({} a (quote b))").unwrap());
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ReaderTable {
+  pub brackets: &'static [ReaderBracket],
+  pub prefixes: &'static [ReaderPrefix],
+}
+
+impl ReaderTable {
+  /// `[a]` for `([] a)`, `{a}` for `({} a)`, and `'a` for `(quote a)`
+  pub const STANDARD: ReaderTable = ReaderTable {
+    brackets: &[
+      ReaderBracket {
+        open: '[',
+        close: ']',
+        head: "[]",
+      },
+      ReaderBracket {
+        open: '{',
+        close: '}',
+        head: "{}",
+      },
+    ],
+    prefixes: &[ReaderPrefix {
+      prefix: '\'',
+      head: "quote",
+    }],
+  };
+
+  pub fn is_empty(&self) -> bool {
+    self.brackets.is_empty() && self.prefixes.is_empty()
+  }
+
+  pub(crate) fn bracket_by_open(&self, c: char) -> Option<&'static ReaderBracket> {
+    self.brackets.iter().find(|b| b.open == c)
+  }
+
+  pub(crate) fn bracket_by_close(&self, c: char) -> Option<&'static ReaderBracket> {
+    self.brackets.iter().find(|b| b.close == c)
+  }
+
+  pub(crate) fn bracket_by_head(&self, head: &str) -> Option<&'static ReaderBracket> {
+    self.brackets.iter().find(|b| b.head == head)
+  }
+
+  pub(crate) fn prefix(&self, c: char) -> Option<&'static ReaderPrefix> {
+    self.prefixes.iter().find(|p| p.prefix == c)
+  }
+
+  pub(crate) fn prefix_by_head(&self, head: &str) -> Option<&'static ReaderPrefix> {
+    self.prefixes.iter().find(|p| p.head == head)
+  }
+
+  /// whether the reader would not read `s` as one bare leaf
+  pub(crate) fn breaks_leaf(&self, s: &str) -> bool {
+    s.chars().any(|c| self.brackets.iter().any(|b| b.open == c || b.close == c))
+      || s.chars().next().is_some_and(|c| self.prefix(c).is_some())
+  }
+}
+
+/// Limits on the code being parsed, for code from untrusted sources. Each limit fails with
+/// its own `CirruErrorKind`, at the position where it is hit. The lexer checks the size of input,
+/// leaves and the number of tokens, building the tree checks nesting, including nesting by indentation,
+/// and the number of top-level expressions. The default only limits nesting, like `parse`.
+/// The steps of `parse` check them with `lex_with_limits`, `resolve_indentations_with_limits`
+/// and `build_exprs_with_limits`.
+///
+/// # Examples
+///
+/// ```
+/// # use cirru_parser::{parse_with_options, CirruErrorKind, ParseLimits, ParseOptions};
+/// let options = ParseOptions {
+///   limits: ParseLimits {
+///     max_leaf_len: Some(8),
+///     ..Default::default()
+///   },
+///   ..Default::default()
+/// };
+/// let error = parse_with_options("println |hello-world", &options).unwrap_err();
+/// assert_eq!(error.kind, CirruErrorKind::LeafTooLong { len: 12, max: 8 });
+/// assert_eq!(error.context.unwrap().pos.column, 9);
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ParseLimits {
+  /// bytes of code, fails with `CirruErrorKind::InputTooLarge`
+  pub max_input_size: Option<usize>,
+  /// levels of nested lists, counting lists created by `$` when it is resolved, fails with `CirruErrorKind::NestingTooDeep`.
+  /// This bounds indentation too, see `ParseOptions::max_indent` for a limit on indentation alone
+  pub max_depth: usize,
+  /// bytes of text in a leaf, after escapes, fails with `CirruErrorKind::LeafTooLong`
+  pub max_leaf_len: Option<usize>,
+  /// tokens from the lexer, counting leaves, parentheses and line starts, fails with `CirruErrorKind::TooManyTokens`
+  pub max_tokens: Option<usize>,
+  /// top-level expressions, fails with `CirruErrorKind::TooManyExpressions`
+  pub max_top_level: Option<usize>,
+}
+
+impl Default for ParseLimits {
+  fn default() -> Self {
+    Self {
+      max_input_size: None,
+      max_depth: MAX_NESTING_DEPTH,
+      max_leaf_len: None,
+      max_tokens: None,
+      max_top_level: None,
+    }
+  }
+}
+
+/// Options for `parse_with_options`, the default is the same as `parse`.
+///
+/// # Examples
+///
+/// ```
+/// # use cirru_parser::{parse, parse_with_options, IndentUnit, ParseOptions};
+/// let options = ParseOptions {
+///   indent: IndentUnit::Tab,
+///   ..Default::default()
+/// };
+/// assert_eq!(parse_with_options("a\n\tb", &options).unwrap(), parse("a\n  b").unwrap());
+/// ```
+#[derive(Clone, Debug)]
+pub struct ParseOptions {
+  pub indent: IndentUnit,
+  pub mixed_indent: MixedIndent,
+  pub whitespace: WhitespacePolicy,
+  /// deepest indentation level allowed, see `Lexer::with_max_indent`.
+  /// It only looks at indentation and is checked by the lexer, so a recovering lexer can go on
+  /// with the line. `limits.max_depth` also counts parentheses and `$`, and a line indented by
+  /// `n` levels is already `n + 1` lists deep, so set this only for a tighter bound on indentation.
+  /// When both are hit on the same line, `IndentationTooDeep` is reported.
+  pub max_indent: Option<usize>,
+  pub reader: ReaderTable,
+  pub comments: CommentPolicy,
+  /// reads `"""` at the end of a line as a block string, with its text on the lines after it indented one more level
+  pub block_strings: bool,
+  /// reads `r"..."` as a raw string, where a backslash is kept as it is, it can not contain `"` or newlines
+  pub raw_strings: bool,
+  /// passes run in order after building the tree, `$` and `,` are kept as leaves without them
+  pub resolvers: Vec<Arc<dyn SugarResolver>>,
+  pub limits: ParseLimits,
+}
+
+impl Default for ParseOptions {
+  fn default() -> Self {
+    Self {
+      indent: IndentUnit::default(),
+      mixed_indent: MixedIndent::default(),
+      whitespace: WhitespacePolicy::default(),
+      max_indent: None,
+      reader: ReaderTable::default(),
+      comments: CommentPolicy::default(),
+      block_strings: false,
+      raw_strings: false,
+      resolvers: Self::default_resolvers(),
+      limits: ParseLimits::default(),
+    }
+  }
+}
+
+impl ParseOptions {
+  /// `DollarSugar` then `CommaSugar`, same as `parse`
+  pub fn default_resolvers() -> Vec<Arc<dyn SugarResolver>> {
+    vec![Arc::new(DollarSugar), Arc::new(CommaSugar)]
+  }
+
+  /// whether `$` nests the items after it, so it counts as a level of nesting
+  pub(crate) fn wraps_dollar(&self) -> bool {
+    self.resolvers.iter().any(|r| r.wraps_dollar())
+  }
+
+  /// character one level of indentation is made of
+  pub(crate) fn indent_char(&self) -> char {
+    match self.indent {
+      IndentUnit::TwoSpaces | IndentUnit::FourSpaces => ' ',
+      IndentUnit::Tab => '\t',
+    }
+  }
+
+  /// columns a tab takes, when rejected it is taken as one level for recovering
+  pub(crate) fn tab_width(&self) -> usize {
+    match self.mixed_indent {
+      MixedIndent::Normalize { tab_width } => tab_width.max(1),
+      MixedIndent::Reject => self.unit_width_of(1),
+    }
+  }
+
+  /// columns of one level
+  pub(crate) fn unit_width(&self) -> usize {
+    self.unit_width_of(self.tab_width())
+  }
+
+  /// indentation of `levels` levels
+  pub(crate) fn indent_text(&self, levels: usize) -> String {
+    match self.indent {
+      IndentUnit::Tab => "\t".repeat(levels),
+      IndentUnit::TwoSpaces | IndentUnit::FourSpaces => " ".repeat(levels * self.unit_width()),
+    }
+  }
+
+  fn unit_width_of(&self, tab_width: usize) -> usize {
+    match self.indent {
+      IndentUnit::TwoSpaces => 2,
+      IndentUnit::FourSpaces => 4,
+      IndentUnit::Tab => tab_width,
+    }
+  }
+}
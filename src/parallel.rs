@@ -0,0 +1,97 @@
+//! Parsing large files on several threads, by lexing pieces between top-level boundaries
+//! in parallel, grouping them into chunks with balanced parentheses, then building chunks
+//! in parallel. See `chunk` for why chunks parse the same as in the whole file.
+
+use std::ops::Range;
+use std::thread;
+
+use crate::chunk::{LexedChunk, LexedPiece, combine_chunks, count_newlines, lex_piece, next_chunk, next_raw_boundary};
+use crate::error::CirruError;
+use crate::primes::Cirru;
+
+/// files smaller than this are parsed on the current thread
+const MIN_PARALLEL_SIZE: usize = 1 << 16;
+
+/// splits `items` into at most `parts` runs of about the same total `size`, keeping their order
+fn split_by_size<T>(items: Vec<T>, parts: usize, size: impl Fn(&T) -> usize) -> Vec<Vec<T>> {
+  let total: usize = items.iter().map(&size).sum();
+  let target = total.div_ceil(parts.max(1)).max(1);
+  let mut runs = vec![];
+  let mut run = vec![];
+  let mut run_size = 0;
+  for item in items {
+    run_size += size(&item);
+    run.push(item);
+    if run_size >= target {
+      runs.push(std::mem::take(&mut run));
+      run_size = 0;
+    }
+  }
+  if !run.is_empty() {
+    runs.push(run);
+  }
+  runs
+}
+
+/// runs `f` on every item, on one thread per run, and keeps the order
+fn map_runs<T: Send, U: Send>(runs: Vec<Vec<T>>, f: impl Fn(T) -> U + Sync) -> Vec<U> {
+  let f = &f;
+  thread::scope(|scope| {
+    let handles = runs
+      .into_iter()
+      .map(|run| scope.spawn(move || run.into_iter().map(f).collect::<Vec<_>>()))
+      .collect::<Vec<_>>();
+    handles
+      .into_iter()
+      .flat_map(|h| h.join().expect("parsing thread panicked"))
+      .collect()
+  })
+}
+
+/// Same as `parse_parallel`, on up to `threads` threads, and for files of any size
+pub fn parse_parallel_with_threads(code: &str, threads: usize) -> Result<Vec<Cirru>, CirruError> {
+  // ranges between raw boundaries, with the lines they start at
+  let mut ranges: Vec<(Range<usize>, usize)> = vec![];
+  let mut start = 0;
+  let mut line = 1;
+  while start < code.len() {
+    let end = next_raw_boundary(code, start);
+    ranges.push((start..end, line));
+    line += count_newlines(&code[start..end]);
+    start = end;
+  }
+
+  let runs = split_by_size(ranges, threads, |(range, _)| range.len());
+  let pieces: Vec<LexedPiece> = map_runs(runs, |(range, line)| lex_piece(code, range, line));
+
+  let mut pieces = pieces.into_iter();
+  let mut chunks: Vec<LexedChunk> = vec![];
+  while let Some(chunk) = next_chunk(&mut pieces) {
+    chunks.push(chunk);
+  }
+
+  let runs = split_by_size(chunks, threads, LexedChunk::size);
+  let parsed = map_runs(runs, LexedChunk::build);
+  combine_chunks(&parsed)
+}
+
+/// Parses Cirru code with top-level expressions spread over the available threads.
+///
+/// The code is split at lines starting at column 0 where parentheses are balanced,
+/// and gives exactly the same tree and errors as `parse`. Small files are parsed on
+/// the current thread.
+///
+/// # Examples
+///
+/// ```
+/// # use cirru_parser::{parse, parse_parallel};
+/// let code = "defn f (x)\n  + x 1\n\nf 2\n".repeat(100);
+/// assert_eq!(parse_parallel(&code), parse(&code));
+/// ```
+pub fn parse_parallel(code: &str) -> Result<Vec<Cirru>, CirruError> {
+  let threads = thread::available_parallelism().map_or(1, |n| n.get());
+  if code.len() < MIN_PARALLEL_SIZE || threads == 1 {
+    return crate::parse(code);
+  }
+  parse_parallel_with_threads(code, threads)
+}
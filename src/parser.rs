@@ -23,30 +23,65 @@ parses to:
 find more on <http://text.cirru.org/> .
 */
 
+mod arena;
+mod borrowed;
+mod builder;
+mod chunk;
+mod comment;
+mod complete;
+mod cst;
 mod error;
+mod incremental;
+mod interner;
+mod lexer;
+mod options;
+#[cfg(feature = "parallel")]
+mod parallel;
 mod primes;
 mod s_expr;
+mod spanned;
+mod stream;
+mod sugar;
 mod tree;
 mod writer;
 
 #[cfg(feature = "serde-json")]
 mod json;
 
-pub use error::{CirruError, CirruErrorKind, ErrorContext, SourcePos};
+pub use arena::{CirruArena, NodeId};
+pub use borrowed::CirruRef;
+pub use comment::{CirruComment, CommentedCirru, CommentedList, CommentedNode};
+pub use complete::{InputStatus, check_input_complete};
+pub use cst::{CirruCst, CstLeafStyle, CstNode};
+pub use error::{CirruError, CirruErrorKind, ErrorContext, SourcePos, Span};
 
 #[cfg(feature = "serde-json")]
 pub use json::*;
 
 const DEFAULT_EXPR_CAPACITY: usize = 8; // Added for default capacity
 
-use std::cmp::Ordering::*;
+/// Deepest nesting of lists accepted when parsing, counting the lists created by `$`,
+/// and the default of `ParseLimits::max_depth`.
+/// Deeper input fails with `CirruErrorKind::NestingTooDeep`, since dropping, comparing or
+/// printing a tree recurses once per level and could overflow the stack.
+pub const MAX_NESTING_DEPTH: usize = 1000;
 
-use primes::CirruLexState;
-use tree::{resolve_comma, resolve_dollar};
+use std::cmp::Ordering::*;
 
-pub use primes::{Cirru, CirruLexItem, CirruLexItemList, escape_cirru_leaf};
+pub use incremental::{CirruDocument, TextEdit};
+pub use interner::LeafInterner;
+pub use lexer::{Lexer, lex, lex_with_limits};
+pub use options::{
+  CommentPolicy, IndentUnit, MixedIndent, ParseLimits, ParseOptions, ReaderBracket, ReaderPrefix, ReaderTable, WhitespacePolicy,
+};
+#[cfg(feature = "parallel")]
+pub use parallel::{parse_parallel, parse_parallel_with_threads};
+pub use primes::{Cirru, CirruLexItem, CirruLexItemList, escape_cirru_leaf, escape_cirru_leaf_with};
 pub use s_expr::format_to_lisp;
-pub use writer::{CirruOneLinerExt, CirruWriterOptions, format, format_expr_one_liner};
+pub use spanned::SpannedCirru;
+pub use stream::CirruReader;
+pub use sugar::{CommaSugar, DollarSugar, SugarResolver};
+pub use writer::{CirruOneLinerExt, CirruWriterOptions, format, format_expr_one_liner, format_with_comments};
 
 /// Helper function to format and print a detailed error
 pub fn print_error(error: &CirruError, source_code: Option<&str>) {
@@ -64,9 +99,30 @@ impl CirruOneLinerParseExt for str {
   }
 }
 
-/// builds a tree from a flat list of tokens
-fn build_exprs(tokens: &[CirruLexItem]) -> Result<Vec<Cirru>, CirruError> {
+/// Builds a tree from tokens with indentation already turned into parentheses by
+/// `resolve_indentations`, the step of `parse` before `$` and `,` are resolved.
+///
+/// # Examples
+///
+/// ```
+/// # use cirru_parser::{build_exprs, lex, resolve_dollar, resolve_indentations, Cirru};
+/// let tokens = resolve_indentations(&lex("a $ b c").unwrap());
+/// let mut tree = build_exprs(&tokens).unwrap();
+/// assert_eq!(tree, vec![Cirru::from(vec!["a", "$", "b", "c"])]);
+/// resolve_dollar(&mut tree);
+/// assert_eq!(tree, vec![Cirru::List(vec!["a".into(), vec!["b", "c"].into()])]);
+/// ```
+pub fn build_exprs(tokens: &[CirruLexItem]) -> Result<Vec<Cirru>, CirruError> {
+  build_exprs_with_limits(tokens, &ParseLimits::default())
+}
+
+/// Same as `build_exprs`, failing when lists are nested deeper than `limits.max_depth`
+/// or there are more top-level expressions than `limits.max_top_level`.
+/// Tokens carry no positions, so these errors have no context, use `parse_with_options`
+/// with `ParseOptions::limits` to get the position where a limit was hit.
+pub fn build_exprs_with_limits(tokens: &[CirruLexItem], limits: &ParseLimits) -> Result<Vec<Cirru>, CirruError> {
   let mut acc: Vec<Cirru> = Vec::with_capacity(tokens.len() / 6 + 1);
+  let mut guard = tree::NestingGuard::new(limits.max_depth, true);
   let mut idx = 0;
   let mut pull_token = || {
     if idx >= tokens.len() {
@@ -84,6 +140,12 @@ fn build_exprs(tokens: &[CirruLexItem]) -> Result<Vec<Cirru>, CirruError> {
       Some(ck) => {
         match ck {
           CirruLexItem::Open => {
+            if let Some(max) = limits.max_top_level
+              && acc.len() >= max
+            {
+              return Err(CirruError::new(CirruErrorKind::TooManyExpressions { max }));
+            }
+            guard.open().map_err(CirruError::new)?;
             let mut pointer: Vec<Cirru> = Vec::with_capacity(DEFAULT_EXPR_CAPACITY);
             // guess a nested level of 16
             let mut pointer_stack: Vec<Vec<Cirru>> = Vec::with_capacity(16);
@@ -95,22 +157,29 @@ fn build_exprs(tokens: &[CirruLexItem]) -> Result<Vec<Cirru>, CirruError> {
                   return Err(CirruError::new(CirruErrorKind::UnexpectedEof));
                 }
                 Some(c) => match c {
-                  CirruLexItem::Close => match pointer_stack.pop() {
-                    None => {
-                      acc.push(Cirru::List(pointer));
-                      break;
+                  CirruLexItem::Close => {
+                    guard.close();
+                    match pointer_stack.pop() {
+                      None => {
+                        acc.push(Cirru::List(pointer));
+                        break;
+                      }
+                      Some(v) => {
+                        let prev_p = pointer;
+                        pointer = v;
+                        pointer.push(Cirru::List(prev_p));
+                      }
                     }
-                    Some(v) => {
-                      let prev_p = pointer;
-                      pointer = v;
-                      pointer.push(Cirru::List(prev_p));
-                    }
-                  },
+                  }
                   CirruLexItem::Open => {
+                    guard.open().map_err(CirruError::new)?;
                     pointer_stack.push(pointer);
                     pointer = Vec::with_capacity(DEFAULT_EXPR_CAPACITY);
                   }
-                  CirruLexItem::Str(s) => pointer.push(Cirru::Leaf((**s).into())),
+                  CirruLexItem::Str(s) => {
+                    guard.leaf(s).map_err(CirruError::new)?;
+                    pointer.push(Cirru::Leaf((**s).into()))
+                  }
                   CirruLexItem::Indent(n) => {
                     return Err(CirruError::new(CirruErrorKind::Other(format!("unknown indent: {n}"))));
                   }
@@ -130,261 +199,6 @@ fn build_exprs(tokens: &[CirruLexItem]) -> Result<Vec<Cirru>, CirruError> {
   }
 }
 
-fn parse_indentation(size: u8, ctx: &LexerContext, code: &str) -> Result<CirruLexItem, CirruError> {
-  if size & 0x1 == 0x0 {
-    // even number
-    Ok(CirruLexItem::Indent(size >> 1))
-  } else {
-    let pos = ctx.current_pos();
-    let snippet = ctx.get_context_snippet(code, 20);
-    let error_ctx = ErrorContext::new(pos, Some(snippet), "checking indentation".to_string());
-    Err(CirruError::with_context(CirruErrorKind::InvalidIndentation(size), error_ctx))
-  }
-}
-
-const DEFAULT_BUFFER_CAPACITY: usize = 8;
-
-/// Position tracker for lexical analysis
-struct LexerContext {
-  line: usize,
-  column: usize,
-  offset: usize,
-}
-
-impl LexerContext {
-  fn new() -> Self {
-    Self {
-      line: 1,
-      column: 1,
-      offset: 0,
-    }
-  }
-
-  fn current_pos(&self) -> SourcePos {
-    SourcePos::new(self.line, self.column, self.offset)
-  }
-
-  fn advance(&mut self, c: char) {
-    self.offset += c.len_utf8();
-    if c == '\n' {
-      self.line += 1;
-      self.column = 1;
-    } else {
-      self.column += 1;
-    }
-  }
-
-  fn get_context_snippet(&self, code: &str, window: usize) -> String {
-    let start = self.offset.saturating_sub(window);
-    let end = (self.offset + window).min(code.len());
-    let snippet = &code[start..end];
-    // Use escape_debug to show special characters like \n, \t, spaces clearly
-    let escaped: String = snippet.chars().take(60).flat_map(|c| c.escape_debug()).collect();
-    format!("...{escaped}...")
-  }
-}
-
-/// The lexer for Cirru syntax. It scans the code and returns a flat list of tokens.
-/// It uses a state machine to handle different parts of the syntax, such as strings,
-/// tokens, and indentation.
-pub fn lex(initial_code: &str) -> Result<CirruLexItemList, CirruError> {
-  // guessed an initial length
-  let mut acc: CirruLexItemList = Vec::with_capacity(initial_code.len() >> 4);
-  let mut state = CirruLexState::Indent;
-  let mut buffer = String::with_capacity(DEFAULT_BUFFER_CAPACITY);
-  let code = initial_code;
-  let mut ctx = LexerContext::new();
-
-  for (_idx, c) in code.char_indices() {
-    match state {
-      CirruLexState::Space => match c {
-        ' ' => {
-          state = CirruLexState::Space;
-          buffer.clear();
-        }
-        '\n' => {
-          state = CirruLexState::Indent;
-          buffer.clear();
-        }
-        '(' => {
-          acc.push(CirruLexItem::Open);
-          state = CirruLexState::Space;
-          buffer = String::new()
-        }
-        ')' => {
-          acc.push(CirruLexItem::Close);
-          state = CirruLexState::Space;
-          buffer.clear()
-        }
-        '"' => {
-          state = CirruLexState::Str;
-          buffer.clear();
-        }
-        _ => {
-          state = CirruLexState::Token;
-          buffer.clear();
-          buffer.push(c);
-        }
-      },
-      CirruLexState::Token => match c {
-        ' ' => {
-          acc.push(CirruLexItem::Str(buffer));
-          state = CirruLexState::Space;
-          buffer = String::with_capacity(DEFAULT_BUFFER_CAPACITY);
-        }
-        '"' => {
-          acc.push(CirruLexItem::Str(buffer));
-          state = CirruLexState::Str;
-          buffer = String::with_capacity(DEFAULT_BUFFER_CAPACITY);
-        }
-        '\n' => {
-          acc.push(CirruLexItem::Str(buffer));
-          state = CirruLexState::Indent;
-          buffer = String::with_capacity(DEFAULT_BUFFER_CAPACITY);
-        }
-        '(' => {
-          acc.push(CirruLexItem::Str(buffer));
-          acc.push(CirruLexItem::Open);
-          state = CirruLexState::Space;
-          buffer = String::new()
-        }
-        ')' => {
-          acc.push(CirruLexItem::Str(buffer));
-          acc.push(CirruLexItem::Close);
-          state = CirruLexState::Space;
-          buffer = String::new()
-        }
-        _ => {
-          state = CirruLexState::Token;
-          buffer.push(c);
-        }
-      },
-      CirruLexState::Str => match c {
-        '"' => {
-          acc.push(CirruLexItem::Str(buffer));
-          state = CirruLexState::Space;
-          buffer = String::with_capacity(DEFAULT_BUFFER_CAPACITY);
-        }
-        '\\' => {
-          state = CirruLexState::Escape;
-        }
-        '\n' => {
-          let pos = ctx.current_pos();
-          let snippet = ctx.get_context_snippet(code, 20);
-          let error_ctx = ErrorContext::new(pos, Some(snippet), "in string literal".to_string());
-          return Err(CirruError::with_context(CirruErrorKind::UnexpectedNewlineInString, error_ctx));
-        }
-        _ => {
-          state = CirruLexState::Str;
-          buffer.push(c);
-        }
-      },
-      CirruLexState::Escape => match c {
-        '"' => {
-          state = CirruLexState::Str;
-          buffer.push('"');
-        }
-        '\'' => {
-          state = CirruLexState::Str;
-          buffer.push('\'');
-        }
-        't' => {
-          state = CirruLexState::Str;
-          buffer.push('\t');
-        }
-        'n' => {
-          state = CirruLexState::Str;
-          buffer.push('\n');
-        }
-        'r' => {
-          state = CirruLexState::Str;
-          buffer.push('\r');
-        }
-        'u' => {
-          // Unicode escaping: not fully supported
-          let pos = ctx.current_pos();
-          let snippet = ctx.get_context_snippet(code, 20);
-          let error_ctx = ErrorContext::new(pos, Some(snippet), "in escape sequence".to_string());
-          return Err(CirruError::with_context(
-            CirruErrorKind::Other("Unicode escape sequences (\\u) are not supported".to_string()),
-            error_ctx,
-          ));
-        }
-        '\\' => {
-          state = CirruLexState::Str;
-          buffer.push('\\');
-        }
-        _ => {
-          let pos = ctx.current_pos();
-          let snippet = ctx.get_context_snippet(code, 20);
-          let error_ctx = ErrorContext::new(pos, Some(snippet), "invalid escape sequence in string".to_string());
-          return Err(CirruError::with_context(CirruErrorKind::InvalidEscape(c), error_ctx));
-        }
-      },
-      CirruLexState::Indent => match c {
-        ' ' => {
-          state = CirruLexState::Indent;
-          buffer.push(c);
-        }
-        '\n' => {
-          state = CirruLexState::Indent;
-          buffer.clear();
-        }
-        '"' => {
-          let level = parse_indentation(buffer.len() as u8, &ctx, code)?;
-          acc.push(level);
-          state = CirruLexState::Str;
-          buffer = String::new();
-        }
-        '(' => {
-          let level = parse_indentation(buffer.len() as u8, &ctx, code)?;
-          acc.push(level);
-          acc.push(CirruLexItem::Open);
-          state = CirruLexState::Space;
-          buffer.clear();
-        }
-        ')' => {
-          let pos = ctx.current_pos();
-          let snippet = ctx.get_context_snippet(code, 20);
-          let error_ctx = ErrorContext::new(pos, Some(snippet), "at line start".to_string());
-          return Err(CirruError::with_context(CirruErrorKind::UnexpectedCloseParen, error_ctx));
-        }
-        _ => {
-          let level = parse_indentation(buffer.len() as u8, &ctx, code)?;
-          acc.push(level);
-          state = CirruLexState::Token;
-          buffer.clear();
-          buffer.push(c);
-        }
-      },
-    }
-
-    ctx.advance(c);
-  }
-
-  match state {
-    CirruLexState::Space => Ok(acc),
-    CirruLexState::Token => {
-      acc.push(CirruLexItem::Str(buffer));
-      Ok(acc)
-    }
-    CirruLexState::Escape => {
-      let pos = ctx.current_pos();
-      let error_ctx = ErrorContext::new(pos, None, "at end of file".to_string());
-      Err(CirruError::with_context(
-        CirruErrorKind::Other("incomplete escape sequence".to_string()),
-        error_ctx,
-      ))
-    }
-    CirruLexState::Indent => Ok(acc),
-    CirruLexState::Str => {
-      let pos = ctx.current_pos();
-      let error_ctx = ErrorContext::new(pos, None, "unclosed string literal".to_string());
-      Err(CirruError::with_context(CirruErrorKind::UnexpectedEof, error_ctx))
-    }
-  }
-}
-
 /// This function transforms a flat list of tokens into a tree structure
 /// by handling indentation. It inserts `Open` and `Close` tokens based on
 /// changes in indentation levels.
@@ -400,7 +214,7 @@ pub fn lex(initial_code: &str) -> Result<CirruLexItemList, CirruError> {
 /// ```
 pub fn resolve_indentations(tokens: &[CirruLexItem]) -> CirruLexItemList {
   let mut acc: CirruLexItemList = Vec::with_capacity(tokens.len() * 2);
-  let mut level: u8 = 0;
+  let mut level: usize = 0;
 
   if tokens.is_empty() {
     return vec![];
@@ -448,7 +262,7 @@ pub fn resolve_indentations(tokens: &[CirruLexItem]) -> CirruLexItemList {
 
   // Close all remaining parenthesis.
   if !acc.is_empty() {
-    let mut new_acc = Vec::with_capacity(1 + acc.len() + level as usize + 1);
+    let mut new_acc = Vec::with_capacity(1 + acc.len() + level + 1);
     new_acc.push(CirruLexItem::Open);
     new_acc.append(&mut acc); // acc is drained
 
@@ -462,14 +276,61 @@ pub fn resolve_indentations(tokens: &[CirruLexItem]) -> CirruLexItemList {
   }
 }
 
+/// Same as `resolve_indentations`, failing when there are more tokens than `limits.max_tokens`,
+/// lines indented deeper than `limits.max_depth` allows, or more lines starting top-level
+/// expressions than `limits.max_top_level`. Tokens carry no positions, so these errors have no context,
+/// use `parse_with_options` with `ParseOptions::limits` to get the position where a limit was hit.
+pub fn resolve_indentations_with_limits(tokens: &[CirruLexItem], limits: &ParseLimits) -> Result<CirruLexItemList, CirruError> {
+  if let Some(max) = limits.max_tokens
+    && tokens.len() > max
+  {
+    return Err(CirruError::new(CirruErrorKind::TooManyTokens { max }));
+  }
+  let mut top_level: usize = 0;
+  for token in tokens {
+    match token {
+      // a line indented by `n` levels is in `n + 1` lists
+      CirruLexItem::Indent(n) if *n >= limits.max_depth => {
+        return Err(CirruError::new(CirruErrorKind::NestingTooDeep {
+          depth: n + 1,
+          max: limits.max_depth,
+        }));
+      }
+      CirruLexItem::Indent(0) => {
+        top_level += 1;
+        if let Some(max) = limits.max_top_level
+          && top_level > max
+        {
+          return Err(CirruError::new(CirruErrorKind::TooManyExpressions { max }));
+        }
+      }
+      _ => (),
+    }
+  }
+  Ok(resolve_indentations(tokens))
+}
+
+/// Resolves `$` in a tree from `build_exprs`: `a $ b c` becomes `a (b c)`.
+pub fn resolve_dollar(xs: &mut Vec<Cirru>) {
+  tree::resolve_dollar(xs);
+}
+
+/// Resolves `,` in a tree from `build_exprs`: items of a list that starts with `,`
+/// are spliced into its parent, so that `a (, b c)` becomes `a b c`.
+/// Runs after `resolve_dollar` in `parse`.
+pub fn resolve_comma(xs: &mut Vec<Cirru>) {
+  tree::resolve_comma(xs);
+}
+
 /// Parses a string of Cirru code into a tree of `Cirru` expressions.
 ///
 /// This is the main entry point for the parser. It performs the following steps:
-/// 1. Lexing: The code is tokenized into a flat list of `CirruLexItem`s.
-/// 2. Indentation Resolution: The flat list of tokens is transformed into a tree
-///    structure by handling indentation.
-/// 3. Tree Building: The token tree is converted into a tree of `Cirru` expressions.
-/// 4. Syntax Resolution: Special syntax like `$` and `,` is resolved.
+/// 1. Tree Building: Tokens are pulled from the lexer and put into lists as they come,
+///    with indentation turned into lists on the way, in a single pass over the code.
+/// 2. Syntax Resolution: Special syntax like `$` and `,` is resolved, see `resolve_dollar` and `resolve_comma`.
+///
+/// The result and errors are the same as running `lex`, `resolve_indentations` and `build_exprs`
+/// in turn, which are kept public to inspect the steps when debugging.
 ///
 /// # Examples
 ///
@@ -490,16 +351,189 @@ pub fn resolve_indentations(tokens: &[CirruLexItem]) -> CirruLexItemList {
 /// assert_eq!(tree, expected);
 /// ```
 pub fn parse(code: &str) -> Result<Vec<Cirru>, CirruError> {
-  let tokens = lex(code)?;
-  let tokens = resolve_indentations(&tokens);
-  // println!("{:?}", tokens);
-  let mut tree = build_exprs(&tokens)?;
-  // println!("tree {:?}", tree);
-  resolve_dollar(&mut tree);
-  resolve_comma(&mut tree);
+  let mut tree = builder::build_tree(Lexer::new(code), |s| Cirru::Leaf(s.into()))?;
+  tree::resolve_dollar(&mut tree);
+  tree::resolve_comma(&mut tree);
+  Ok(tree)
+}
+
+/// Parses Cirru code like `parse`, but every node carries the `Span` of source code it came from.
+///
+/// Leaves cover their text, including quotes. Lists from parentheses cover the parentheses,
+/// lists from indentation cover their children, and lists created by `$` start at the `$`.
+///
+/// # Examples
+///
+/// ```
+/// # use cirru_parser::{parse_with_spans, SpannedCirru};
+/// let tree = parse_with_spans("a (b c)").unwrap();
+/// let SpannedCirru::List(xs, _) = &tree[0] else { unreachable!() };
+/// assert_eq!(xs[1].span().start.column, 3);
+/// assert_eq!(xs[1].span().end.column, 8);
+/// ```
+pub fn parse_with_spans(code: &str) -> Result<Vec<SpannedCirru>, CirruError> {
+  let tokens = Lexer::new(code).collect::<Result<Vec<_>, _>>()?;
+  let tokens = spanned::resolve_indentations_spanned(&tokens);
+  let mut tree = spanned::build_spanned_exprs(&tokens, None)?;
+  tree::resolve_dollar(&mut tree);
+  tree::resolve_comma(&mut tree);
   Ok(tree)
 }
 
+/// Parses Cirru code like `parse`, but leaves borrow their text from `code` instead of
+/// allocating, which suits read-only passes over large files. Strings with escapes are
+/// the only leaves that own their text. Use `CirruRef::to_cirru` to get the owned tree.
+///
+/// # Examples
+///
+/// ```
+/// # use std::borrow::Cow;
+/// # use cirru_parser::{parse, parse_borrowed, CirruRef};
+/// let code = "println \"a\\tb\" c";
+/// let tree = parse_borrowed(code).unwrap();
+/// let CirruRef::List(xs) = &tree[0] else { unreachable!() };
+/// assert!(matches!(&xs[0], CirruRef::Leaf(Cow::Borrowed("println"))));
+/// assert!(matches!(&xs[1], CirruRef::Leaf(Cow::Owned(s)) if s == "a\tb"));
+/// assert_eq!(tree[0].to_cirru(), parse(code).unwrap()[0]);
+/// ```
+pub fn parse_borrowed(code: &str) -> Result<Vec<CirruRef<'_>>, CirruError> {
+  let mut tree = builder::build_tree(Lexer::new(code), CirruRef::Leaf)?;
+  tree::resolve_dollar(&mut tree);
+  tree::resolve_comma(&mut tree);
+  Ok(tree)
+}
+
+/// Parses Cirru code like `parse`, but leaves of the same text share one `Arc<str>`
+/// from `interner`, also with trees parsed earlier with the same interner.
+///
+/// # Examples
+///
+/// ```
+/// # use std::sync::Arc;
+/// # use cirru_parser::{parse_with_interner, Cirru, LeafInterner};
+/// let mut interner = LeafInterner::new();
+/// let a = parse_with_interner("defn f (x) x", &mut interner).unwrap();
+/// let b = parse_with_interner("defn g (y) y", &mut interner).unwrap();
+/// let (Cirru::List(xs), Cirru::List(ys)) = (&a[0], &b[0]) else { unreachable!() };
+/// let (Cirru::Leaf(x), Cirru::Leaf(y)) = (&xs[0], &ys[0]) else { unreachable!() };
+/// assert!(Arc::ptr_eq(x, y));
+/// assert_eq!(interner.len(), 5);
+/// ```
+pub fn parse_with_interner(code: &str, interner: &mut LeafInterner) -> Result<Vec<Cirru>, CirruError> {
+  let mut tree = builder::build_tree(Lexer::new(code), |s| Cirru::Leaf(interner.intern(&s)))?;
+  tree::resolve_dollar(&mut tree);
+  tree::resolve_comma(&mut tree);
+  Ok(tree)
+}
+
+/// Parses Cirru code like `parse`, with the indentation rules in `options`.
+///
+/// # Examples
+///
+/// ```
+/// # use cirru_parser::{parse, parse_with_options, IndentUnit, MixedIndent, ParseOptions};
+/// let options = ParseOptions {
+///   indent: IndentUnit::FourSpaces,
+///   mixed_indent: MixedIndent::Normalize { tab_width: 4 },
+///   ..Default::default()
+/// };
+/// let tree = parse_with_options("a\n    b\n\t\tc", &options).unwrap();
+/// assert_eq!(tree, parse("a\n  b\n    c").unwrap());
+/// assert!(parse_with_options("a\n  b", &options).is_err());
+/// ```
+pub fn parse_with_options(code: &str, options: &ParseOptions) -> Result<Vec<Cirru>, CirruError> {
+  let mut tree = builder::build_tree(Lexer::new(code).with_options(options), |s| Cirru::Leaf(s.into()))?;
+  for resolver in &options.resolvers {
+    resolver.resolve(&mut tree);
+  }
+  if options.comments == CommentPolicy::Strip {
+    comment::strip_comments(&mut tree);
+  }
+  Ok(tree)
+}
+
+/// Parses Cirru code like `parse_with_options`, with comments taken out of the tree
+/// and attached to the expressions after them. `format_with_comments` writes them back.
+///
+/// # Examples
+///
+/// ```
+/// # use cirru_parser::{parse, parse_with_comments, ParseOptions};
+/// let code = "; entry\ndefn main ()\n  ; say hi\n  println |hi\n";
+/// let tree = parse_with_comments(code, &ParseOptions::default()).unwrap();
+/// assert_eq!(tree.items[0].comments[0].text(), "entry");
+/// assert_eq!(tree.to_cirru(), parse("defn main ()\n  println |hi").unwrap());
+/// assert_eq!(tree.to_cirru_with_comments(), parse(code).unwrap());
+/// ```
+pub fn parse_with_comments(code: &str, options: &ParseOptions) -> Result<CommentedList, CirruError> {
+  let options = ParseOptions {
+    comments: CommentPolicy::Keep,
+    ..options.to_owned()
+  };
+  Ok(CommentedList::from_cirru(&parse_with_options(code, &options)?))
+}
+
+/// Parses Cirru code into a lossless concrete syntax tree.
+///
+/// The tree keeps indentation, blank lines, quoting of leaves, explicit parentheses,
+/// and the `$` and `,` markers, so `to_source` reproduces the input exactly.
+/// Use `CirruCst::lower` to get the same tree `parse` returns.
+///
+/// # Examples
+///
+/// ```
+/// # use cirru_parser::{parse, parse_cst};
+/// let code = "a $ b \"c\"\n\n  , d ; e\n";
+/// let cst = parse_cst(code).unwrap();
+/// assert_eq!(cst.to_source(), code);
+/// assert_eq!(cst.lower(), parse(code).unwrap());
+/// ```
+pub fn parse_cst(code: &str) -> Result<CirruCst, CirruError> {
+  let tokens = Lexer::new(code).collect::<Result<Vec<_>, _>>()?;
+  let tokens = spanned::resolve_indentations_spanned(&tokens);
+  cst::build_cst(code, &tokens)
+}
+
+/// Parses Cirru code without stopping at the first error, for editors that need an outline
+/// of files being typed. Returns the best-effort tree with every error found, sorted by position.
+///
+/// Broken regions are repaired: odd indentation is rounded down, unclosed strings end at line end,
+/// invalid escapes are kept as they are, stray `)` are dropped, and parentheses left open are
+/// closed at the end of their top-level expression. For valid code, the tree is the same as `parse`.
+///
+/// # Examples
+///
+/// ```
+/// # use cirru_parser::{parse_recovering, Cirru};
+/// let (tree, errors) = parse_recovering("a (b\nc \"d\ne");
+/// assert_eq!(errors.len(), 2);
+/// assert_eq!(tree.len(), 3);
+/// assert_eq!(tree[2], Cirru::List(vec!["e".into()]));
+/// ```
+pub fn parse_recovering(code: &str) -> (Vec<Cirru>, Vec<CirruError>) {
+  let mut errors: Vec<CirruError> = vec![];
+  let mut tokens = Vec::with_capacity(code.len() >> 4);
+  for item in Lexer::recovering(code) {
+    match item {
+      Ok(token) => tokens.push(token),
+      Err(e) => errors.push(e),
+    }
+  }
+
+  let mut tree: Vec<Cirru> = vec![];
+  for group in spanned::split_top_level_groups(&tokens) {
+    let group = spanned::resolve_indentations_spanned(group);
+    if let Ok(xs) = spanned::build_spanned_exprs(&group, Some(&mut errors)) {
+      tree.extend(xs.into_iter().map(Cirru::from));
+    }
+  }
+  tree::resolve_dollar(&mut tree);
+  tree::resolve_comma(&mut tree);
+
+  errors.sort_by_key(|e| e.context.as_ref().map_or(usize::MAX, |ctx| ctx.pos.offset));
+  (tree, errors)
+}
+
 /// Backward compatibility function that returns Result with String error
 #[deprecated(since = "0.2.0", note = "Use parse() instead which provides better error information")]
 pub fn parse_compat(code: &str) -> Result<Vec<Cirru>, String> {
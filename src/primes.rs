@@ -165,7 +165,8 @@ impl Cirru {
     }
   }
 
-  /// expression of `; a` or `;; a` are treated as comment
+  /// expression of `; a` or `;; a` are treated as comment,
+  /// see `parse_with_comments` for comments attached to expressions
   pub fn is_comment(&self) -> bool {
     match self {
       Cirru::List(_) => false,
@@ -179,6 +180,8 @@ pub enum CirruLexState {
   Space,
   Token,
   Escape,
+  /// after `\u` in a string, the `{` and hex digits of a code point
+  UnicodeEscape,
   Indent,
   Str,
 }
@@ -190,8 +193,8 @@ pub enum CirruLexItem {
   Open,
   /// `)`
   Close,
-  // supposed to be enough with indentation of 255
-  Indent(u8),
+  /// indentation level of a line, by 2 spaces
+  Indent(usize),
   Str(String),
 }
 
@@ -201,8 +204,8 @@ impl From<&str> for CirruLexItem {
   }
 }
 
-impl From<u8> for CirruLexItem {
-  fn from(value: u8) -> Self {
+impl From<usize> for CirruLexItem {
+  fn from(value: usize) -> Self {
     Self::Indent(value)
   }
 }
@@ -229,25 +232,62 @@ pub type CirruLexItemList = Vec<CirruLexItem>;
 /// escape_cirru_leaf("a b"); // "\"a b\""
 /// ```
 pub fn escape_cirru_leaf(s: &str) -> String {
+  escape_cirru_leaf_with(s, false)
+}
+
+/// Like `escape_cirru_leaf`, and with `unicode_escapes` control and invisible characters,
+/// such as zero-width spaces, are written as `\u{..}` escapes.
+/// ```rust
+/// use cirru_parser::escape_cirru_leaf_with;
+/// assert_eq!(escape_cirru_leaf_with("a\u{200b}b", true), "\"a\\u{200b}b\"");
+/// ```
+pub fn escape_cirru_leaf_with(s: &str, unicode_escapes: bool) -> String {
   let mut chunk = String::with_capacity(s.len() + 1);
   chunk.push('\"');
   if CirruLexItem::is_normal_str(s) {
     chunk.push_str(s);
   } else {
     for c in s.chars() {
-      match c {
-        '\n' => chunk.push_str("\\n"),
-        '\t' => chunk.push_str("\\t"),
-        '\"' => chunk.push_str("\\\""),
-        '\\' => chunk.push_str("\\\\"),
-        _ => chunk.push(c),
-      }
+      push_escaped_char(&mut chunk, c, unicode_escapes);
     }
   }
   chunk.push('"');
   chunk
 }
 
+/// control characters and format characters that are not visible in text
+pub(crate) fn is_invisible_char(c: char) -> bool {
+  c.is_control()
+    || matches!(
+      c,
+      '\u{ad}'
+        | '\u{61c}'
+        | '\u{180e}'
+        | '\u{200b}'..='\u{200f}'
+        | '\u{2028}'..='\u{202e}'
+        | '\u{2060}'..='\u{206f}'
+        | '\u{feff}'
+        | '\u{fff9}'..='\u{fffb}'
+        | '\u{e0000}'..='\u{e007f}'
+    )
+}
+
+/// pushes a character of a string literal, escaped when needed
+pub(crate) fn push_escaped_char(buf: &mut String, c: char, unicode_escapes: bool) {
+  match c {
+    '\n' => buf.push_str("\\n"),
+    '\t' => buf.push_str("\\t"),
+    '\"' => buf.push_str("\\\""),
+    '\\' => buf.push_str("\\\\"),
+    '\r' if unicode_escapes => buf.push_str("\\r"),
+    _ if unicode_escapes && is_invisible_char(c) => {
+      use std::fmt::Write;
+      write!(buf, "\\u{{{:x}}}", c as u32).expect("write to string");
+    }
+    _ => buf.push(c),
+  }
+}
+
 impl Serialize for Cirru {
   fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
   where
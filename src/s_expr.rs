@@ -11,51 +11,95 @@ pub fn format_to_lisp(xs: &[Cirru]) -> Result<String, String> {
   Ok(content)
 }
 
+/// a list being formatted, with the index of the next item and the text so far
+struct LispFrame<'a> {
+  xs: &'a [Cirru],
+  idx: usize,
+  indent: usize,
+  chunk: String,
+}
+
+/// Formats an expression, nested lists are kept on a stack instead of recursing
 pub fn format_expr(node: &Cirru, indent: usize) -> Result<String, String> {
-  match node {
-    Cirru::List(xs) => {
-      if !xs.is_empty() && xs[0].is_comment() {
-        let mut chunk: String = format!("{}{}", gen_newline(indent), ";;");
-        for (idx, x) in xs.iter().enumerate() {
-          if idx > 0 {
-            chunk = format!("{chunk} {x}");
-          }
+  let xs = match node {
+    Cirru::List(xs) if !xs.is_empty() && xs[0].is_comment() => return Ok(format_comment(xs, indent)),
+    Cirru::List(xs) => xs,
+    Cirru::Leaf(token) => return format_leaf(token),
+  };
+  let mut stack = vec![LispFrame {
+    xs,
+    idx: 0,
+    indent,
+    chunk: String::from("("),
+  }];
+
+  loop {
+    let frame = stack.last_mut().expect("stack is not empty");
+    let next = match frame.xs.get(frame.idx) {
+      None => {
+        let done = stack.pop().expect("frame checked");
+        let text = format!("{})", done.chunk);
+        match stack.last_mut() {
+          Some(_) => text,
+          None => return Ok(text),
         }
-        chunk = format!("{}{}", chunk.trim_end(), gen_newline(indent));
-        Ok(chunk)
-      } else {
-        let mut chunk = String::from("(");
-        for (idx, x) in xs.iter().enumerate() {
-          if x.is_nested() {
-            chunk = format!("{}{}", chunk.trim_end(), gen_newline(indent + 1));
-          }
-          let next = format_expr(x, indent + 1)?;
-          if next.starts_with('\n') {
-            chunk = format!("{}{}", chunk.trim_end(), next);
-          } else {
-            chunk = format!("{chunk}{next}");
-          }
-          // TODO dirty way, but intuitive for now
-          if idx < xs.len() - 1 && !ends_with_newline(&chunk) {
-            chunk = format!("{chunk} ");
+      }
+      Some(x) => {
+        if x.is_nested() {
+          frame.chunk = format!("{}{}", frame.chunk.trim_end(), gen_newline(frame.indent + 1));
+        }
+        match x {
+          Cirru::List(ys) if !ys.is_empty() && ys[0].is_comment() => format_comment(ys, frame.indent + 1),
+          Cirru::List(ys) => {
+            let indent = frame.indent + 1;
+            stack.push(LispFrame {
+              xs: ys,
+              idx: 0,
+              indent,
+              chunk: String::from("("),
+            });
+            continue;
           }
+          Cirru::Leaf(token) => format_leaf(token)?,
         }
-        Ok(format!("{chunk})"))
       }
+    };
+
+    let frame = stack.last_mut().expect("parent of finished item");
+    if next.starts_with('\n') {
+      frame.chunk = format!("{}{}", frame.chunk.trim_end(), next);
+    } else {
+      frame.chunk = format!("{}{}", frame.chunk, next);
     }
-    Cirru::Leaf(token) => {
-      if token.is_empty() {
-        Err(String::from("empty string is invalid"))
-      } else {
-        let s0 = token.chars().next().unwrap();
-        if s0 == '|' || s0 == '"' {
-          Ok(format!("\"{}\"", token[1..].escape_default()))
-        } else if token.contains(' ') || token.contains('\n') || token.contains('\"') {
-          Err(format!("bad token content: {token}"))
-        } else {
-          Ok((**token).to_string())
-        }
-      }
+    // TODO dirty way, but intuitive for now
+    if frame.idx < frame.xs.len() - 1 && !ends_with_newline(&frame.chunk) {
+      frame.chunk = format!("{} ", frame.chunk);
+    }
+    frame.idx += 1;
+  }
+}
+
+fn format_comment(xs: &[Cirru], indent: usize) -> String {
+  let mut chunk: String = format!("{}{}", gen_newline(indent), ";;");
+  for (idx, x) in xs.iter().enumerate() {
+    if idx > 0 {
+      chunk = format!("{chunk} {x}");
+    }
+  }
+  format!("{}{}", chunk.trim_end(), gen_newline(indent))
+}
+
+fn format_leaf(token: &str) -> Result<String, String> {
+  if token.is_empty() {
+    Err(String::from("empty string is invalid"))
+  } else {
+    let s0 = token.chars().next().unwrap();
+    if s0 == '|' || s0 == '"' {
+      Ok(format!("\"{}\"", token[1..].escape_default()))
+    } else if token.contains(' ') || token.contains('\n') || token.contains('\"') {
+      Err(format!("bad token content: {token}"))
+    } else {
+      Ok(token.to_string())
     }
   }
 }
@@ -0,0 +1,305 @@
+use std::sync::Arc;
+
+use crate::MAX_NESTING_DEPTH;
+use crate::error::{CirruError, CirruErrorKind, ErrorContext, Span, report};
+use crate::primes::{Cirru, CirruLexItem};
+use crate::tree::{NestingGuard, SugarNode};
+
+use std::cmp::Ordering::*;
+
+/// Cirru tree in which every node remembers the range of source code it came from
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SpannedCirru {
+  Leaf(Arc<str>, Span),
+  List(Vec<SpannedCirru>, Span),
+}
+
+impl SpannedCirru {
+  /// source range of this node
+  pub fn span(&self) -> Span {
+    match self {
+      Self::Leaf(_, span) => *span,
+      Self::List(_, span) => *span,
+    }
+  }
+
+  /// drops spans and returns the plain tree
+  pub fn to_cirru(&self) -> Cirru {
+    match self {
+      Self::Leaf(s, _) => Cirru::Leaf(s.to_owned()),
+      Self::List(xs, _) => Cirru::List(xs.iter().map(Self::to_cirru).collect()),
+    }
+  }
+}
+
+impl From<SpannedCirru> for Cirru {
+  fn from(value: SpannedCirru) -> Self {
+    match value {
+      SpannedCirru::Leaf(s, _) => Cirru::Leaf(s),
+      SpannedCirru::List(xs, _) => Cirru::List(xs.into_iter().map(Cirru::from).collect()),
+    }
+  }
+}
+
+impl SugarNode for SpannedCirru {
+  fn is_leaf_text(&self, s: &str) -> bool {
+    matches!(self, Self::Leaf(t, _) if &**t == s)
+  }
+
+  fn children(&self) -> Option<&[Self]> {
+    match self {
+      Self::List(xs, _) => Some(xs),
+      Self::Leaf(..) => None,
+    }
+  }
+
+  fn children_mut(&mut self) -> Option<&mut Vec<Self>> {
+    match self {
+      Self::List(xs, _) => Some(xs),
+      Self::Leaf(..) => None,
+    }
+  }
+
+  fn into_children(self) -> Vec<Self> {
+    match self {
+      Self::List(xs, _) => xs,
+      Self::Leaf(..) => vec![],
+    }
+  }
+
+  /// the new list covers the `$` and everything after it
+  fn wrap_dollar(dollar: Self, items: Vec<Self>) -> Self {
+    let start = dollar.span().start;
+    let end = items.last().map_or(dollar.span().end, |x| x.span().end);
+    Self::List(items, Span::new(start, end))
+  }
+}
+
+/// Same as `resolve_indentations`, but keeps spans. Tokens created from indentation
+/// have zero-width spans, so they can be told apart from parentheses in the source.
+pub(crate) fn resolve_indentations_spanned(tokens: &[(CirruLexItem, Span)]) -> Vec<(CirruLexItem, Span)> {
+  let mut acc: Vec<(CirruLexItem, Span)> = Vec::with_capacity(tokens.len() * 2);
+  let mut level: usize = 0;
+
+  let Some((_, first_span)) = tokens.first() else {
+    return vec![];
+  };
+
+  for (token, span) in tokens {
+    match token {
+      CirruLexItem::Indent(n) => {
+        let at = Span::empty_at(span.end);
+        match n.cmp(&level) {
+          Greater => {
+            for _ in 0..(n - level) {
+              acc.push((CirruLexItem::Open, at));
+            }
+          }
+          Less => {
+            for _ in 0..(level - n) {
+              acc.push((CirruLexItem::Close, at));
+            }
+            acc.push((CirruLexItem::Close, at));
+            acc.push((CirruLexItem::Open, at));
+          }
+          Equal => {
+            if !acc.is_empty() {
+              acc.push((CirruLexItem::Close, at));
+              acc.push((CirruLexItem::Open, at));
+            }
+          }
+        }
+        level = *n;
+      }
+      _ => acc.push((token.to_owned(), *span)),
+    }
+  }
+
+  if acc.is_empty() {
+    return vec![];
+  }
+
+  let head = Span::empty_at(first_span.end);
+  let tail = Span::empty_at(acc.last().map_or(head.end, |(_, span)| span.end));
+  let mut new_acc = Vec::with_capacity(1 + acc.len() + level + 1);
+  new_acc.push((CirruLexItem::Open, head));
+  new_acc.append(&mut acc);
+  for _ in 0..=level {
+    new_acc.push((CirruLexItem::Close, tail));
+  }
+  new_acc
+}
+
+/// a list under construction, with the span of the token that opened it
+struct OpenList {
+  items: Vec<SpannedCirru>,
+  open: Span,
+}
+
+impl OpenList {
+  fn new(open: Span) -> Self {
+    Self { items: vec![], open }
+  }
+
+  /// lists from parentheses cover the parentheses, lists from indentation cover their children
+  fn finish(self, close: Span) -> SpannedCirru {
+    let start = if self.open.is_empty() {
+      self.items.first().map_or(self.open.start, |x| x.span().start)
+    } else {
+      self.open.start
+    };
+    let end = if close.is_empty() {
+      self.items.last().map_or(start, |x| x.span().end)
+    } else {
+      close.end
+    };
+    SpannedCirru::List(self.items, Span::new(start, end))
+  }
+}
+
+/// Same as `build_exprs`, but keeps spans. Errors carry the position of the offending token.
+/// With `errors` provided, problems are collected there and the tree is repaired:
+/// stray `)` are skipped, leaves at top level start a new expression, expressions
+/// left open at the end are closed, and lists or `$` nested too deep are left out
+/// with their items kept in the enclosing list.
+pub(crate) fn build_spanned_exprs(
+  tokens: &[(CirruLexItem, Span)],
+  mut errors: Option<&mut Vec<CirruError>>,
+) -> Result<Vec<SpannedCirru>, CirruError> {
+  let mut acc: Vec<SpannedCirru> = Vec::with_capacity(tokens.len() / 6 + 1);
+  let mut stack: Vec<OpenList> = Vec::with_capacity(16);
+  let mut guard = NestingGuard::new(MAX_NESTING_DEPTH, true);
+  // lists left out for being too deep, which are still open
+  let mut skipped: usize = 0;
+
+  for (token, span) in tokens {
+    match token {
+      CirruLexItem::Open => match guard.open() {
+        Ok(()) => stack.push(OpenList::new(*span)),
+        Err(kind) => {
+          let error_ctx = ErrorContext::new(span.start, None, "building expressions".to_string());
+          report(&mut errors, CirruError::with_context(kind, error_ctx))?;
+          skipped += 1;
+        }
+      },
+      CirruLexItem::Close if skipped > 0 => skipped -= 1,
+      CirruLexItem::Close => match stack.pop() {
+        None => {
+          let error_ctx = ErrorContext::new(span.start, None, "at top level".to_string());
+          report(
+            &mut errors,
+            CirruError::with_context(CirruErrorKind::UnexpectedCloseParen, error_ctx),
+          )?;
+        }
+        Some(list) => {
+          guard.close();
+          let node = list.finish(*span);
+          match stack.last_mut() {
+            Some(parent) => parent.items.push(node),
+            None => acc.push(node),
+          }
+        }
+      },
+      CirruLexItem::Str(s) => {
+        if stack.is_empty() {
+          let error_ctx = ErrorContext::new(span.start, None, "at top level".to_string());
+          report(
+            &mut errors,
+            CirruError::with_context(CirruErrorKind::Other(format!("unknown item: {token:?}")), error_ctx),
+          )?;
+          // at top level, so it is not too deep
+          guard.open().ok();
+          stack.push(OpenList::new(Span::empty_at(span.start)));
+        }
+        if let Err(kind) = guard.leaf(s) {
+          let error_ctx = ErrorContext::new(span.start, None, "building expressions".to_string());
+          report(&mut errors, CirruError::with_context(kind, error_ctx))?;
+          continue;
+        }
+        if let Some(list) = stack.last_mut() {
+          list.items.push(SpannedCirru::Leaf((**s).into(), *span));
+        }
+      }
+      CirruLexItem::Indent(n) => {
+        return Err(CirruError::new(CirruErrorKind::Other(format!("unknown indent: {n}"))));
+      }
+    }
+  }
+
+  // point at the innermost `(` left open, lists from indentation are always closed
+  if let Some(list) = stack.iter().rev().find(|list| !list.open.is_empty()).or(stack.first()) {
+    let error_ctx = ErrorContext::new(list.open.start, None, "unclosed expression".to_string());
+    report(&mut errors, CirruError::with_context(CirruErrorKind::UnexpectedEof, error_ctx))?;
+    let end = tokens.last().map_or(list.open.end, |(_, span)| span.end);
+    while let Some(list) = stack.pop() {
+      let node = list.finish(Span::empty_at(end));
+      match stack.last_mut() {
+        Some(parent) => parent.items.push(node),
+        None => acc.push(node),
+      }
+    }
+  }
+
+  Ok(acc)
+}
+
+/// Splits lexed tokens into groups of top-level expressions that are resolved on their own
+/// when recovering from errors. A group starts at an `Indent(0)` and ends before the next one,
+/// unless it leaves parentheses open which later lines close, like `a (b` followed by `c)`.
+/// A group whose parentheses are never balanced stays alone, so a missing `)` does not swallow
+/// the rest of the file.
+pub(crate) fn split_top_level_groups(tokens: &[(CirruLexItem, Span)]) -> Vec<&[(CirruLexItem, Span)]> {
+  // segments between `Indent(0)` tokens, with the balance of parentheses in each
+  let mut segments: Vec<(usize, usize, i64)> = vec![];
+  let mut start = 0;
+  let mut balance: i64 = 0;
+  for (idx, (token, _)) in tokens.iter().enumerate() {
+    match token {
+      CirruLexItem::Indent(0) if idx > start => {
+        segments.push((start, idx, balance));
+        start = idx;
+        balance = 0;
+      }
+      CirruLexItem::Open => balance += 1,
+      CirruLexItem::Close => balance -= 1,
+      _ => (),
+    }
+  }
+  if start < tokens.len() {
+    segments.push((start, tokens.len(), balance));
+  }
+
+  // balance of all segments before each one, and for each, the first later one where it is not above
+  let mut before = Vec::with_capacity(segments.len() + 1);
+  before.push(0);
+  for (_, _, b) in &segments {
+    before.push(before[before.len() - 1] + b);
+  }
+  let mut closing: Vec<Option<usize>> = vec![None; before.len()];
+  let mut lower: Vec<usize> = vec![];
+  for k in (0..before.len()).rev() {
+    while lower.last().is_some_and(|&m| before[m] > before[k]) {
+      lower.pop();
+    }
+    closing[k] = lower.last().copied();
+    lower.push(k);
+  }
+
+  let mut groups = Vec::with_capacity(segments.len());
+  let mut i = 0;
+  while i < segments.len() {
+    let (from, mut to, balance) = segments[i];
+    let mut next = i + 1;
+    // joined up to the segment that closes its parentheses, not when a stray `)` comes first
+    if balance > 0
+      && let Some(m) = closing[i]
+      && before[m] == before[i]
+    {
+      to = segments[m - 1].1;
+      next = m;
+    }
+    groups.push(&tokens[from..to]);
+    i = next;
+  }
+  groups
+}
@@ -0,0 +1,173 @@
+use std::collections::VecDeque;
+use std::io::{BufRead, BufReader, Read};
+
+use crate::builder;
+use crate::chunk::count_newlines;
+use crate::error::{CirruError, CirruErrorKind, ErrorContext, SourcePos};
+use crate::lexer::{LexToken, Lexer, SNIPPET_WINDOW};
+use crate::primes::Cirru;
+use crate::tree::{resolve_comma, resolve_dollar};
+
+/// Reads top-level expressions from a stream, one at a time.
+///
+/// Lines are buffered until a line starting at column 0 shows the expressions before it are finished,
+/// and their parentheses are balanced or have a stray `)`, so memory is bounded by the largest top-level expression.
+/// Each expression is parsed like `parse` would in the whole input, and errors carry positions
+/// in the whole input. Their snippets show the input around them as far as it has been read,
+/// which is one line past the expression. Reading stops after the first error.
+///
+/// # Examples
+///
+/// ```
+/// # use cirru_parser::{parse, CirruReader};
+/// let input = "defn f (x)\n  + x 1\n\nf 2\n";
+/// let exprs = CirruReader::new(input.as_bytes()).collect::<Result<Vec<_>, _>>().unwrap();
+/// assert_eq!(exprs, parse(input).unwrap());
+/// ```
+pub struct CirruReader<R> {
+  input: R,
+  /// code of top-level expressions that are not finished yet
+  code: String,
+  /// end of the input before `code`, for error snippets
+  before: String,
+  line: String,
+  /// start of the lines after the last column 0 line in `code`
+  piece_start: usize,
+  /// explicit `(` minus explicit `)` in `code` before `piece_start`
+  balance: i64,
+  /// line of input where `code` starts, 1-indexed
+  start_line: usize,
+  /// byte offset in input where `code` starts
+  start_offset: usize,
+  parsed: VecDeque<Cirru>,
+  done: bool,
+}
+
+impl<R: BufRead> CirruReader<R> {
+  pub fn new(input: R) -> Self {
+    Self {
+      input,
+      code: String::new(),
+      before: String::new(),
+      line: String::new(),
+      piece_start: 0,
+      balance: 0,
+      start_line: 1,
+      start_offset: 0,
+      parsed: VecDeque::new(),
+      done: false,
+    }
+  }
+}
+
+impl<R: Read> CirruReader<BufReader<R>> {
+  /// reads from an unbuffered source
+  pub fn from_read(input: R) -> Self {
+    Self::new(BufReader::new(input))
+  }
+}
+
+/// explicit `(` minus explicit `)`, `None` when the code has a lexing error
+fn paren_balance(code: &str) -> Option<i64> {
+  let mut lexer = Lexer::new(code);
+  let mut balance = 0;
+  while let Some(item) = lexer.next_token() {
+    match item.ok()?.0 {
+      LexToken::Open => balance += 1,
+      LexToken::Close => balance -= 1,
+      _ => (),
+    }
+  }
+  Some(balance)
+}
+
+impl<R: BufRead> CirruReader<R> {
+  /// position in the whole input of the end of buffered code
+  fn end_pos(&self) -> SourcePos {
+    let lines = count_newlines(&self.code);
+    let column = self.code.rsplit('\n').next().map_or(0, |s| s.chars().count()) + 1;
+    SourcePos::new(self.start_line + lines, column, self.start_offset + self.code.len())
+  }
+
+  /// parses buffered code, which starts at a top-level boundary and has balanced parentheses
+  fn flush(&mut self) -> Result<(), CirruError> {
+    // code is lexed between the input before it and the line read after it, so a BOM is only
+    // skipped at the start of input, and error snippets show the same text as in the whole input
+    let start = self.before.len();
+    let end = start + self.code.len();
+    let text = [self.before.as_str(), &self.code, &self.line].concat();
+    let result = builder::build_tree(Lexer::for_range(&text, start..end, self.start_line), |s| Cirru::Leaf(s.into()));
+    match result {
+      Ok(mut xs) => {
+        resolve_dollar(&mut xs);
+        resolve_comma(&mut xs);
+        self.parsed.extend(xs);
+        let mut tail = end.saturating_sub(SNIPPET_WINDOW);
+        while !text.is_char_boundary(tail) {
+          tail -= 1;
+        }
+        self.before = text[tail..end].to_string();
+        self.start_line += count_newlines(&self.code);
+        self.start_offset += self.code.len();
+        self.code.clear();
+        self.piece_start = 0;
+        self.balance = 0;
+        Ok(())
+      }
+      Err(mut error) => {
+        if let Some(ctx) = &mut error.context {
+          ctx.pos.offset = ctx.pos.offset - start + self.start_offset;
+        }
+        Err(error)
+      }
+    }
+  }
+}
+
+impl<R: BufRead> Iterator for CirruReader<R> {
+  type Item = Result<Cirru, CirruError>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    loop {
+      if let Some(x) = self.parsed.pop_front() {
+        return Some(Ok(x));
+      }
+      if self.done {
+        return None;
+      }
+      self.line.clear();
+      let result = match self.input.read_line(&mut self.line) {
+        Err(e) => {
+          let error_ctx = ErrorContext::new(self.end_pos(), None, "reading input".to_string());
+          Err(CirruError::with_context(CirruErrorKind::Io(e.to_string()), error_ctx))
+        }
+        Ok(0) => {
+          self.done = true;
+          self.flush()
+        }
+        Ok(_) => {
+          let at_boundary = self.line.starts_with(|c: char| !c.is_whitespace());
+          let mut result = Ok(());
+          if at_boundary && !self.code.is_empty() {
+            // the lines since the last boundary are complete, and lex the same as in the whole input
+            match paren_balance(&self.code[self.piece_start..]) {
+              Some(balance) => self.balance += balance,
+              None => self.balance = 0,
+            }
+            self.piece_start = self.code.len();
+            // a stray `)` can not be closed by later lines, so it fails without reading them
+            if self.balance <= 0 {
+              result = self.flush();
+            }
+          }
+          self.code.push_str(&self.line);
+          result
+        }
+      };
+      if let Err(error) = result {
+        self.done = true;
+        return Some(Err(error));
+      }
+    }
+  }
+}
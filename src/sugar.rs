@@ -0,0 +1,93 @@
+use std::fmt;
+
+use crate::primes::Cirru;
+use crate::tree;
+
+/// A pass rewriting the tree after `build_exprs`, for syntax sugar like `$` and `,`.
+///
+/// Resolvers run in the order they are listed in `ParseOptions::resolvers`.
+/// Functions and closures taking `&mut Vec<Cirru>` are resolvers too.
+///
+/// # Examples
+///
+/// ```
+/// # use std::sync::Arc;
+/// # use cirru_parser::{parse_with_options, Cirru, ParseOptions, SugarResolver};
+/// /// `a |> f` becomes `f a`, the item before `|>` is passed to the rest of the line
+/// #[derive(Debug)]
+/// struct Pipe;
+///
+/// impl SugarResolver for Pipe {
+///   fn resolve(&self, xs: &mut Vec<Cirru>) {
+///     for x in xs.iter_mut() {
+///       if let Cirru::List(ys) = x {
+///         self.resolve(ys);
+///         if let Some(p) = ys.iter().position(|y| y.eq_leaf("|>")) {
+///           let mut rest = ys.split_off(p);
+///           rest.remove(0);
+///           rest.append(ys);
+///           *ys = rest;
+///         }
+///       }
+///     }
+///   }
+/// }
+///
+/// let mut options = ParseOptions::default();
+/// options.resolvers.push(Arc::new(Pipe));
+/// let tree = parse_with_options("x |> f $ g y", &options).unwrap();
+/// assert_eq!(tree, vec![Cirru::List(vec!["f".into(), vec!["g", "y"].into(), "x".into()])]);
+/// ```
+pub trait SugarResolver: Send + Sync {
+  fn resolve(&self, xs: &mut Vec<Cirru>);
+
+  /// whether the pass wraps the items after `$` into a list like `DollarSugar` does,
+  /// so each `$` counts as a level of nesting for `ParseLimits::max_depth`
+  fn wraps_dollar(&self) -> bool {
+    false
+  }
+
+  /// name for debugging
+  fn name(&self) -> &str {
+    std::any::type_name::<Self>()
+  }
+}
+
+impl fmt::Debug for dyn SugarResolver {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.write_str(self.name())
+  }
+}
+
+impl<F> SugarResolver for F
+where
+  F: Fn(&mut Vec<Cirru>) + Send + Sync,
+{
+  fn resolve(&self, xs: &mut Vec<Cirru>) {
+    self(xs)
+  }
+}
+
+/// `a $ b c` becomes `a (b c)`, see `resolve_dollar`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DollarSugar;
+
+impl SugarResolver for DollarSugar {
+  fn resolve(&self, xs: &mut Vec<Cirru>) {
+    tree::resolve_dollar(xs);
+  }
+
+  fn wraps_dollar(&self) -> bool {
+    true
+  }
+}
+
+/// `a (, b c)` becomes `a b c`, see `resolve_comma`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CommaSugar;
+
+impl SugarResolver for CommaSugar {
+  fn resolve(&self, xs: &mut Vec<Cirru>) {
+    tree::resolve_comma(xs);
+  }
+}
@@ -1,7 +1,54 @@
+use crate::error::CirruErrorKind;
 use crate::primes;
 
 pub use primes::Cirru;
 
+/// Tree nodes that the `$` and `,` passes know how to rewrite,
+/// so that trees carrying extra data share the same resolution logic.
+pub(crate) trait SugarNode: Sized {
+  /// checks if the node is a leaf with exactly this text
+  fn is_leaf_text(&self, s: &str) -> bool;
+  /// children of a list node, `None` for leaves
+  fn children(&self) -> Option<&[Self]>;
+  /// mutable children of a list node, `None` for leaves
+  fn children_mut(&mut self) -> Option<&mut Vec<Self>>;
+  /// takes the children out of a list node, leaves give an empty list
+  fn into_children(self) -> Vec<Self>;
+  /// builds the list that `$` creates from the items following it
+  fn wrap_dollar(dollar: Self, items: Vec<Self>) -> Self;
+}
+
+impl SugarNode for Cirru {
+  fn is_leaf_text(&self, s: &str) -> bool {
+    self.eq_leaf(s)
+  }
+
+  fn children(&self) -> Option<&[Self]> {
+    match self {
+      Cirru::List(xs) => Some(xs),
+      Cirru::Leaf(_) => None,
+    }
+  }
+
+  fn children_mut(&mut self) -> Option<&mut Vec<Self>> {
+    match self {
+      Cirru::List(xs) => Some(xs),
+      Cirru::Leaf(_) => None,
+    }
+  }
+
+  fn into_children(self) -> Vec<Self> {
+    match self {
+      Cirru::List(xs) => xs,
+      Cirru::Leaf(_) => vec![],
+    }
+  }
+
+  fn wrap_dollar(_dollar: Self, items: Vec<Self>) -> Self {
+    Cirru::List(items)
+  }
+}
+
 /// Resolves comma syntax in-place.
 ///
 /// The comma syntax is a way to flatten nested expressions.
@@ -16,55 +63,44 @@ pub use primes::Cirru;
 /// ```
 ///
 /// which is parsed as `[a, [",", b, c]]`, will be transformed into `[a, b, c]`.
-pub fn resolve_comma(xs: &mut Vec<Cirru>) {
-  loop {
-    let mut comma_pos: Option<usize> = None;
-    // Find a comma expression, which is a list starting with a comma leaf.
-    // For example: `[`,`, `item1`, `item2`, ...]`
-    for (i, x) in xs.iter().enumerate() {
-      if let Cirru::List(ys) = x
-        && let Some(Cirru::Leaf(s)) = ys.first()
-        && &**s == ","
-      {
-        comma_pos = Some(i);
-        break;
+pub(crate) fn resolve_comma<T: SugarNode>(xs: &mut Vec<T>) {
+  // lists are resolved from the outside in, after commas at their level are spliced
+  let mut lists: Vec<&mut Vec<T>> = vec![xs];
+  while let Some(ys) = lists.pop() {
+    splice_commas(ys);
+    for y in ys {
+      if let Some(zs) = y.children_mut() {
+        lists.push(zs);
       }
     }
-
-    // If a comma expression is found, its children are moved to the current level.
-    if let Some(p) = comma_pos {
-      let mut to_insert = match xs.remove(p) {
-        Cirru::List(ys) => ys,
-        _ => unreachable!(),
-      };
-      to_insert.remove(0);
-      for (i, item) in to_insert.into_iter().enumerate() {
-        xs.insert(p + i, item);
-      }
-    } else {
-      break;
-    }
   }
+}
 
-  let mut i = 0;
-  while i < xs.len() {
-    if let Cirru::Leaf(s) = &xs[i] {
-      if &**s == "," {
-        xs.remove(i);
-      } else {
-        i += 1;
-      }
-    } else {
-      i += 1;
-    }
+/// Splices children of comma expressions into the list, also when comma expressions are nested,
+/// and drops `,` leaves, in one scan.
+fn splice_commas<T: SugarNode>(xs: &mut Vec<T>) {
+  let is_comma_expr = |x: &T| x.children().and_then(|ys| ys.first()).is_some_and(|y| y.is_leaf_text(","));
+  if !xs.iter().any(|x| x.is_leaf_text(",") || is_comma_expr(x)) {
+    return;
   }
-
-  // Recursively resolve commas in nested lists first.
-  for x in &mut *xs {
-    if let Cirru::List(ys) = x {
-      resolve_comma(ys);
+  let mut result = Vec::with_capacity(xs.len());
+  // items left to take, in the list and in the comma expressions being spliced
+  let mut sources = vec![std::mem::take(xs).into_iter()];
+  while let Some(source) = sources.last_mut() {
+    match source.next() {
+      Some(x) if is_comma_expr(&x) => {
+        let mut children = x.into_children().into_iter();
+        children.next();
+        sources.push(children);
+      }
+      Some(x) if x.is_leaf_text(",") => (),
+      Some(x) => result.push(x),
+      None => {
+        sources.pop();
+      }
     }
   }
+  *xs = result;
 }
 
 /// Resolves dollar syntax in-place.
@@ -81,34 +117,84 @@ pub fn resolve_comma(xs: &mut Vec<Cirru>) {
 ///
 /// This will be transformed from `[defn, fib, [x], [fib, $, dec, x]]`
 /// to `[defn, fib, [x], [fib, [dec, x]]]`
-pub fn resolve_dollar(xs: &mut Vec<Cirru>) {
-  // Recursively resolve dollars in nested lists first.
-  for x in &mut *xs {
-    if let Cirru::List(ys) = x {
-      resolve_dollar(ys);
+pub(crate) fn resolve_dollar<T: SugarNode>(xs: &mut Vec<T>) {
+  // `$` only wraps items of its own list, so lists can be resolved in any order
+  let mut lists: Vec<&mut Vec<T>> = vec![xs];
+  while let Some(ys) = lists.pop() {
+    wrap_dollars(ys);
+    for y in ys {
+      if let Some(zs) = y.children_mut() {
+        lists.push(zs);
+      }
+    }
+  }
+}
+
+/// Wraps items after each `$` into a list, from the rightmost `$` to the left,
+/// so every item is moved once.
+fn wrap_dollars<T: SugarNode>(xs: &mut Vec<T>) {
+  let mut wrapped: Option<T> = None;
+  while let Some(p) = xs.iter().rposition(|x| x.is_leaf_text("$")) {
+    let mut items = xs.split_off(p + 1);
+    let dollar = xs.pop().expect("dollar position checked");
+    items.extend(wrapped.take());
+    wrapped = Some(T::wrap_dollar(dollar, items));
+  }
+  xs.extend(wrapped);
+}
+
+/// Depth of lists while a tree is being built, counting the lists that `$` will wrap items into,
+/// so that trees too deep to be dropped, compared or formatted safely are rejected early.
+pub(crate) struct NestingGuard {
+  max: usize,
+  /// whether `$` is resolved later, otherwise it is a plain leaf
+  dollar: bool,
+  /// for each open list, 1 plus the number of `$` in it so far
+  levels: Vec<usize>,
+  depth: usize,
+}
+
+impl NestingGuard {
+  pub(crate) fn new(max: usize, dollar: bool) -> Self {
+    Self {
+      max,
+      dollar,
+      levels: Vec::with_capacity(16),
+      depth: 0,
+    }
+  }
+
+  fn enter(&mut self) -> Result<(), CirruErrorKind> {
+    if self.depth >= self.max {
+      return Err(CirruErrorKind::NestingTooDeep {
+        depth: self.depth + 1,
+        max: self.max,
+      });
     }
+    self.depth += 1;
+    Ok(())
   }
 
-  loop {
-    let mut dollar_pos: Option<usize> = None;
-    // Find the first occurrence of a dollar sign leaf from the right.
-    for (i, x) in xs.iter().enumerate().rev() {
-      if let Cirru::Leaf(s) = x
-        && &**s == "$"
-      {
-        dollar_pos = Some(i);
-        break;
+  pub(crate) fn open(&mut self) -> Result<(), CirruErrorKind> {
+    self.enter()?;
+    self.levels.push(1);
+    Ok(())
+  }
+
+  /// a `$` adds a level for the items after it
+  pub(crate) fn leaf(&mut self, text: &str) -> Result<(), CirruErrorKind> {
+    if self.dollar && text == "$" && !self.levels.is_empty() {
+      self.enter()?;
+      if let Some(level) = self.levels.last_mut() {
+        *level += 1;
       }
     }
+    Ok(())
+  }
 
-    // If a dollar sign is found, the rest of the elements in the list
-    // are wrapped into a new nested list.
-    if let Some(p) = dollar_pos {
-      let mut new_list = vec![Cirru::List(xs.drain(p + 1..).collect())];
-      xs.truncate(p);
-      xs.append(&mut new_list);
-    } else {
-      break;
+  pub(crate) fn close(&mut self) {
+    if let Some(level) = self.levels.pop() {
+      self.depth -= level;
     }
   }
 }
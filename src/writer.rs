@@ -1,4 +1,6 @@
-use crate::primes::Cirru;
+use crate::comment::CommentedList;
+use crate::options::ReaderTable;
+use crate::primes::{Cirru, is_invisible_char, push_escaped_char};
 use std::fmt;
 use std::str;
 
@@ -62,7 +64,7 @@ fn is_char_allowed(x: char) -> bool {
   ALLOWED_CHARS.find(x).is_some()
 }
 
-fn generate_leaf(s: &str) -> String {
+fn generate_leaf(s: &str, options: CirruWriterOptions) -> String {
   let mut all_allowed = true;
   for x in s.chars() {
     if !is_char_allowed(x) {
@@ -70,44 +72,121 @@ fn generate_leaf(s: &str) -> String {
       break;
     }
   }
-  if all_allowed {
+  if all_allowed && !options.reader.breaks_leaf(s) {
     s.to_string()
+  } else if options.raw_strings && s.contains('\\') && !s.contains('"') && !s.chars().any(is_invisible_char) {
+    format!("r\"{s}\"")
   } else {
     let mut ret = String::with_capacity(s.len() + 2);
     ret.push('"');
     for c in s.chars() {
-      match c {
-        '\n' => ret.push_str("\\n"),
-        '\t' => ret.push_str("\\t"),
-        '\"' => ret.push_str("\\\""),
-        '\\' => ret.push_str("\\\\"),
-        _ => ret.push(c),
-      }
+      push_escaped_char(&mut ret, c, options.unicode_escapes);
     }
     ret.push('"');
     ret
   }
 }
 
+/// whether the text reads back the same from a block string, and looks better there
+fn fits_block_string(s: &str, options: CirruWriterOptions) -> bool {
+  s.contains('\n')
+    && !s.rsplit('\n').next().is_some_and(|line| line.trim().is_empty())
+    && s.split('\n').all(|line| line.is_empty() || !line.trim().is_empty())
+    && !s
+      .chars()
+      .any(|c| c != '\n' && c != '\t' && (c.is_control() || (options.unicode_escapes && is_invisible_char(c))))
+}
+
+/// `"""` marker and the text on lines indented one more level than the line of the marker
+fn generate_block_string(s: &str, line_level: usize) -> String {
+  let mut ret = String::from("\"\"\"");
+  for line in s.split('\n') {
+    if line.is_empty() {
+      ret.push('\n');
+    } else {
+      ret.push_str(&render_newline(line_level + 1));
+      ret.push_str(line);
+    }
+  }
+  ret
+}
+
 fn generate_empty_expr() -> String {
   String::from("()")
 }
 
-fn generate_inline_expr(xs: &[Cirru]) -> String {
-  let mut result = String::from(CHAR_OPEN);
+/// a list being written inline, with the items left and its closing bracket
+struct InlineFrame<'a> {
+  items: std::slice::Iter<'a, Cirru>,
+  close: char,
+  first: bool,
+}
 
-  for (idx, x) in xs.iter().enumerate() {
-    if idx > 0 {
-      result.push(' ');
+/// Writes the opening of a list written inline and pushes its items to `stack`.
+/// `(quote x)` is written as `'x`, the form after a prefix is not empty so it is not read as a leaf.
+fn open_inline_expr<'a>(mut xs: &'a [Cirru], options: CirruWriterOptions, result: &mut String, stack: &mut Vec<InlineFrame<'a>>) {
+  loop {
+    let head = match xs.first() {
+      Some(Cirru::Leaf(s)) if !options.reader.is_empty() => Some(&**s),
+      _ => None,
+    };
+    if let Some(prefix) = head.and_then(|h| options.reader.prefix_by_head(h))
+      && xs.len() == 2
+    {
+      match &xs[1] {
+        Cirru::Leaf(s) => {
+          let piece = generate_leaf(s, options);
+          if !piece.is_empty() {
+            result.push(prefix.prefix);
+            result.push_str(&piece);
+            return;
+          }
+        }
+        Cirru::List(ys) => {
+          result.push(prefix.prefix);
+          xs = ys;
+          continue;
+        }
+      }
     }
-    let piece = match x {
-      Cirru::Leaf(s) => generate_leaf(s),
-      Cirru::List(ys) => generate_inline_expr(ys),
+    let (open, close, items) = match head.and_then(|h| options.reader.bracket_by_head(h)) {
+      Some(bracket) => (bracket.open, bracket.close, &xs[1..]),
+      None => (CHAR_OPEN, CHAR_CLOSE, xs),
     };
-    result.push_str(&piece)
+    result.push(open);
+    stack.push(InlineFrame {
+      items: items.iter(),
+      close,
+      first: true,
+    });
+    return;
   }
+}
 
-  result.push(CHAR_CLOSE);
+/// writes a list in one line with parentheses, nested lists are kept on a stack instead of recursing
+fn generate_inline_expr(xs: &[Cirru], options: CirruWriterOptions) -> String {
+  let mut result = String::new();
+  let mut stack: Vec<InlineFrame> = vec![];
+  open_inline_expr(xs, options, &mut result, &mut stack);
+
+  while let Some(frame) = stack.last_mut() {
+    match frame.items.next() {
+      Some(x) => {
+        if !frame.first {
+          result.push(' ');
+        }
+        frame.first = false;
+        match x {
+          Cirru::Leaf(s) => result.push_str(&generate_leaf(s, options)),
+          Cirru::List(ys) => open_inline_expr(ys, options, &mut result, &mut stack),
+        }
+      }
+      None => {
+        result.push(frame.close);
+        stack.pop();
+      }
+    }
+  }
   result
 }
 
@@ -125,43 +204,94 @@ fn render_newline(n: usize) -> String {
   ret
 }
 
-fn generate_statement_one_liner(xs: &[Cirru]) -> String {
+fn generate_statement_one_liner(xs: &[Cirru], options: CirruWriterOptions) -> String {
   let mut ret = String::new();
-  let len = xs.len();
-  for (idx, cursor) in xs.iter().enumerate() {
-    if idx > 0 {
-      ret.push(' ');
-    }
-    let at_tail = idx == len - 1 && idx > 0;
-    match cursor {
-      Cirru::Leaf(s) => ret.push_str(&generate_leaf(s)),
-      Cirru::List(ys) => {
-        if at_tail {
-          // Use $ syntax for tail expressions
-          if ys.is_empty() {
-            ret.push('$');
+  let mut xs = xs;
+  loop {
+    let len = xs.len();
+    // a list at the tail is written after `$` in the same line
+    let mut tail: Option<&[Cirru]> = None;
+    for (idx, cursor) in xs.iter().enumerate() {
+      if idx > 0 {
+        ret.push(' ');
+      }
+      let at_tail = idx == len - 1 && idx > 0;
+      match cursor {
+        Cirru::Leaf(s) => ret.push_str(&generate_leaf(s, options)),
+        Cirru::List(ys) => {
+          if at_tail {
+            // Use $ syntax for tail expressions
+            if ys.is_empty() {
+              ret.push('$');
+            } else {
+              ret.push_str("$ ");
+              tail = Some(ys);
+            }
           } else {
-            ret.push_str("$ ");
-            ret.push_str(&generate_statement_one_liner(ys));
+            ret.push_str(&generate_inline_expr(ys, options));
           }
-        } else {
-          ret.push_str(&generate_inline_expr(ys));
         }
       }
     }
+    match tail {
+      Some(ys) => xs = ys,
+      None => return ret,
+    }
   }
-  ret
 }
 
-/// options for writer, `use_inline` for more compact format.
-#[derive(Clone, Copy)]
+/// options for writer, `use_inline` for more compact format,
+/// `unicode_escapes` for writing control and invisible characters in leaves as `\u{..}`.
+/// With a `reader` table, lists written inline use its brackets and prefixes,
+/// like `[a b]` for `([] a b)`, and leaves the reader would split are quoted.
+/// `block_strings` writes leaves with newlines as block strings when they end a line,
+/// and `raw_strings` writes leaves with backslashes as raw strings, see `ParseOptions`.
+///
+/// More options may be added, so it is built from `bool` for `use_inline` and the `with_*` methods.
+///
+/// ```
+/// # use cirru_parser::{format, parse, CirruWriterOptions};
+/// let options = CirruWriterOptions::from(true).with_unicode_escapes(true);
+/// assert_eq!(format(&parse("a (b \"\u{200b}\")").unwrap(), options).unwrap(), "\na $ b \"\\u{200b}\"\n");
+/// ```
+#[derive(Clone, Copy, Default)]
+#[non_exhaustive]
 pub struct CirruWriterOptions {
   pub use_inline: bool,
+  pub unicode_escapes: bool,
+  pub reader: ReaderTable,
+  pub block_strings: bool,
+  pub raw_strings: bool,
 }
 
 impl From<bool> for CirruWriterOptions {
   fn from(use_inline: bool) -> Self {
-    CirruWriterOptions { use_inline }
+    CirruWriterOptions {
+      use_inline,
+      ..Default::default()
+    }
+  }
+}
+
+impl CirruWriterOptions {
+  pub fn with_unicode_escapes(mut self, unicode_escapes: bool) -> Self {
+    self.unicode_escapes = unicode_escapes;
+    self
+  }
+
+  pub fn with_reader(mut self, reader: ReaderTable) -> Self {
+    self.reader = reader;
+    self
+  }
+
+  pub fn with_block_strings(mut self, block_strings: bool) -> Self {
+    self.block_strings = block_strings;
+    self
+  }
+
+  pub fn with_raw_strings(mut self, raw_strings: bool) -> Self {
+    self.raw_strings = raw_strings;
+    self
   }
 }
 
@@ -182,49 +312,106 @@ fn get_node_kind(cursor: &Cirru) -> WriterNode {
   }
 }
 
-fn generate_tree(
-  xs: &[Cirru],
+/// how the text of a nested tree is put in the line of its parent
+#[derive(Clone, Copy)]
+enum TreeWrap {
+  AsIs,
+  /// after `$`, with a space unless the text starts a new line
+  Dollar,
+  /// on a new line at this level
+  Newline(usize),
+  /// on a new line at this level, unless the text starts a new line
+  NewlineUnlessStarted(usize),
+}
+
+impl TreeWrap {
+  fn apply(self, content: String) -> String {
+    match self {
+      Self::AsIs => content,
+      Self::Dollar => {
+        // If content starts with newline, don't add space after $
+        let mut ret = String::from(if content.starts_with('\n') { "$" } else { "$ " });
+        ret.push_str(&content);
+        ret
+      }
+      Self::NewlineUnlessStarted(_) if content.starts_with('\n') => content,
+      Self::Newline(level) | Self::NewlineUnlessStarted(level) => {
+        let mut ret = render_newline(level);
+        ret.push_str(&content);
+        ret
+      }
+    }
+  }
+}
+
+/// a list being written by `generate_tree`, with the state of the loop over its items
+struct TreeFrame<'a> {
+  xs: &'a [Cirru],
+  idx: usize,
   insist_head: bool,
-  options: CirruWriterOptions,
-  base_level: usize,
+  level: usize,
   in_tail: bool,
-) -> Result<String, String> {
-  let mut prev_kind = WriterNode::Nil;
-  let mut level = base_level;
-  let mut result = String::from("");
+  prev_kind: WriterNode,
+  result: String,
+  wrap: TreeWrap,
+}
 
-  for (idx, cursor) in xs.iter().enumerate() {
-    let kind = get_node_kind(cursor);
+/// text of an item, or a nested tree to write first
+enum TreeChild<'a> {
+  Text(String),
+  Tree(TreeFrame<'a>),
+}
+
+impl<'a> TreeFrame<'a> {
+  fn new(xs: &'a [Cirru], insist_head: bool, level: usize, in_tail: bool, wrap: TreeWrap) -> Self {
+    TreeFrame {
+      xs,
+      idx: 0,
+      insist_head,
+      level,
+      in_tail,
+      prev_kind: WriterNode::Nil,
+      result: String::from(""),
+      wrap,
+    }
+  }
+
+  fn kind(&self) -> WriterNode {
+    get_node_kind(&self.xs[self.idx])
+  }
+
+  fn at_tail(&self) -> bool {
+    self.idx != 0 && !self.in_tail && self.prev_kind == WriterNode::Leaf && self.idx == self.xs.len() - 1
+  }
+
+  fn bended(&self) -> bool {
+    self.kind() == WriterNode::Leaf && (self.prev_kind == WriterNode::BoxedExpr || self.prev_kind == WriterNode::Expr)
+  }
+
+  /// writes the item at `idx`, nested trees are returned to be written before it
+  fn child(&self, options: CirruWriterOptions) -> Result<TreeChild<'a>, String> {
+    let xs = self.xs;
+    let (idx, level, prev_kind) = (self.idx, self.level, self.prev_kind);
+    let kind = self.kind();
     let next_level = level + 1;
     let child_insist_head = (prev_kind == WriterNode::BoxedExpr) || (prev_kind == WriterNode::Expr);
-    let at_tail = idx != 0 && !in_tail && prev_kind == WriterNode::Leaf && idx == xs.len() - 1;
-
-    // println!("\nloop {:?} {:?}", prev_kind, kind);
-    // println!("cursor {:?} {} {}", cursor, idx, insist_head);
-    // println!("{:?}", result);
+    let at_tail = self.at_tail();
 
-    let child: String = match cursor {
-      Cirru::Leaf(s) => generate_leaf(s),
+    let text = match &xs[idx] {
+      // nothing follows the last leaf on its line, so the text can go on the lines after it
+      Cirru::Leaf(s) if options.block_strings && idx == xs.len() - 1 && fits_block_string(s, options) => {
+        generate_block_string(s, if self.bended() { next_level } else { level })
+      }
+      Cirru::Leaf(s) => generate_leaf(s, options),
       Cirru::List(ys) => {
         if at_tail {
           if ys.is_empty() {
             String::from("$")
           } else {
-            let content = generate_tree(ys, false, options, level, at_tail)?;
-            if content.starts_with('\n') {
-              // If content starts with newline, don't add space after $
-              let mut ret = String::from("$");
-              ret.push_str(&content);
-              ret
-            } else {
-              // Otherwise, add space after $
-              let mut ret = String::from("$ ");
-              ret.push_str(&content);
-              ret
-            }
+            return Ok(TreeChild::Tree(TreeFrame::new(ys, false, level, at_tail, TreeWrap::Dollar)));
           }
-        } else if idx == 0 && insist_head {
-          generate_inline_expr(ys)
+        } else if idx == 0 && self.insist_head {
+          generate_inline_expr(ys, options)
         } else if kind == WriterNode::Leaf {
           if idx == 0 {
             let mut ret = render_newline(level);
@@ -235,41 +422,40 @@ fn generate_tree(
           }
         } else if kind == WriterNode::SimpleExpr {
           if prev_kind == WriterNode::Leaf {
-            generate_inline_expr(ys)
+            generate_inline_expr(ys, options)
           } else if options.use_inline && prev_kind == WriterNode::SimpleExpr {
             let mut ret = String::from(" ");
-            ret.push_str(&generate_inline_expr(ys));
+            ret.push_str(&generate_inline_expr(ys, options));
             ret
           } else {
-            let mut ret = render_newline(next_level);
-            ret.push_str(&generate_tree(ys, child_insist_head, options, next_level, false)?);
-            ret
+            let wrap = TreeWrap::Newline(next_level);
+            return Ok(TreeChild::Tree(TreeFrame::new(ys, child_insist_head, next_level, false, wrap)));
           }
         } else if kind == WriterNode::Expr {
-          let content = generate_tree(ys, child_insist_head, options, next_level, false)?;
-          if content.starts_with('\n') {
-            content
-          } else {
-            let mut ret = render_newline(next_level);
-            ret.push_str(&content);
-            ret
-          }
+          let wrap = TreeWrap::NewlineUnlessStarted(next_level);
+          return Ok(TreeChild::Tree(TreeFrame::new(ys, child_insist_head, next_level, false, wrap)));
         } else if kind == WriterNode::BoxedExpr {
-          let content = generate_tree(ys, child_insist_head, options, next_level, false)?;
-          if prev_kind == WriterNode::Nil || prev_kind == WriterNode::Leaf || prev_kind == WriterNode::SimpleExpr {
-            content
+          let wrap = if prev_kind == WriterNode::Nil || prev_kind == WriterNode::Leaf || prev_kind == WriterNode::SimpleExpr {
+            TreeWrap::AsIs
           } else {
-            let mut ret = render_newline(next_level);
-            ret.push_str(&content);
-            ret
-          }
+            TreeWrap::Newline(next_level)
+          };
+          return Ok(TreeChild::Tree(TreeFrame::new(ys, child_insist_head, next_level, false, wrap)));
         } else {
           return Err(String::from("Unpected condition"));
         }
       }
     };
+    Ok(TreeChild::Text(text))
+  }
 
-    let bended = kind == WriterNode::Leaf && (prev_kind == WriterNode::BoxedExpr || prev_kind == WriterNode::Expr);
+  /// appends the text of the item at `idx` and moves to the next item
+  fn push_child(&mut self, child: String, options: CirruWriterOptions) {
+    let kind = self.kind();
+    let prev_kind = self.prev_kind;
+    let next_level = self.level + 1;
+    let at_tail = self.at_tail();
+    let bended = self.bended();
 
     let chunk = if at_tail
       || (prev_kind == WriterNode::Leaf && kind == WriterNode::Leaf)
@@ -288,36 +474,62 @@ fn generate_tree(
       child
     };
 
-    result.push_str(&chunk);
+    self.result.push_str(&chunk);
 
     // update writer states
 
     if kind == WriterNode::SimpleExpr {
-      if idx == 0 && insist_head {
-        prev_kind = WriterNode::SimpleExpr;
+      if self.idx == 0 && self.insist_head {
+        self.prev_kind = WriterNode::SimpleExpr;
       } else if options.use_inline {
         if prev_kind == WriterNode::Leaf || prev_kind == WriterNode::SimpleExpr {
-          prev_kind = WriterNode::SimpleExpr;
+          self.prev_kind = WriterNode::SimpleExpr;
         } else {
-          prev_kind = WriterNode::Expr;
+          self.prev_kind = WriterNode::Expr;
         }
       } else if prev_kind == WriterNode::Leaf {
-        prev_kind = WriterNode::SimpleExpr;
+        self.prev_kind = WriterNode::SimpleExpr;
       } else {
-        prev_kind = WriterNode::Expr;
+        self.prev_kind = WriterNode::Expr;
       }
     } else {
-      prev_kind = kind;
+      self.prev_kind = kind;
     }
 
     if bended {
-      level += 1;
+      self.level += 1;
     }
+    self.idx += 1;
+  }
+}
 
-    // console.log("chunk", JSON.stringify(chunk));
-    // console.log("And result", JSON.stringify(result));
+/// Writes a list as lines of code. Nested lists written as lines are kept on a stack
+/// of frames instead of recursing, so deep trees do not overflow the stack.
+fn generate_tree(
+  xs: &[Cirru],
+  insist_head: bool,
+  options: CirruWriterOptions,
+  base_level: usize,
+  in_tail: bool,
+) -> Result<String, String> {
+  let mut stack = vec![TreeFrame::new(xs, insist_head, base_level, in_tail, TreeWrap::AsIs)];
+
+  while let Some(frame) = stack.last_mut() {
+    if frame.idx == frame.xs.len() {
+      let done = stack.pop().expect("frame checked");
+      let text = done.wrap.apply(done.result);
+      match stack.last_mut() {
+        Some(parent) => parent.push_child(text, options),
+        None => return Ok(text),
+      }
+      continue;
+    }
+    match frame.child(options)? {
+      TreeChild::Text(text) => frame.push_child(text, options),
+      TreeChild::Tree(nested) => stack.push(nested),
+    }
   }
-  Ok(result)
+  unreachable!("returns when the outermost list is written")
 }
 
 fn generate_statements(ys: &[Cirru], options: CirruWriterOptions) -> Result<String, String> {
@@ -340,6 +552,11 @@ pub fn format(xs: &[Cirru], options: CirruWriterOptions) -> Result<String, Strin
   generate_statements(xs, options)
 }
 
+/// format Cirru code with comments put back before the expressions they are attached to
+pub fn format_with_comments(xs: &CommentedList, options: CirruWriterOptions) -> Result<String, String> {
+  generate_statements(&xs.to_cirru_with_comments(), options)
+}
+
 /// Format a single Cirru expression as a single line without newlines or indentation.
 ///
 /// Note: the top-level expression (a `Cirru::List`) is rendered without wrapping parentheses,
@@ -347,7 +564,7 @@ pub fn format(xs: &[Cirru], options: CirruWriterOptions) -> Result<String, Strin
 pub fn format_expr_one_liner(expr: &Cirru) -> Result<String, String> {
   match expr {
     Cirru::Leaf(_) => Err(String::from("format_expr_one_liner expects an expr (list)")),
-    Cirru::List(cs) => Ok(generate_statement_one_liner(cs)),
+    Cirru::List(cs) => Ok(generate_statement_one_liner(cs, CirruWriterOptions::default())),
   }
 }
 
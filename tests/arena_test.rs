@@ -0,0 +1,50 @@
+extern crate cirru_parser;
+
+mod common;
+
+use cirru_parser::{Cirru, CirruArena, parse};
+
+#[test]
+fn arena_parents_on_files() -> Result<(), String> {
+  for (_, code) in common::cirru_files() {
+    let arena = CirruArena::from_cirru(&parse(&code)?);
+    // every node is a child of its parent
+    for id in arena.ids() {
+      match arena.parent(id) {
+        Some(p) => assert!(arena.children(p).contains(&id)),
+        None => assert!(arena.roots().contains(&id)),
+      }
+    }
+  }
+  Ok(())
+}
+
+#[test]
+fn arena_building() {
+  let mut arena = CirruArena::new();
+  assert!(arena.is_empty());
+  let root = arena.push_list(None);
+  let a = arena.push_leaf(Some(root), "a");
+  let inner = arena.push_list(Some(root));
+  let b = arena.push_leaf(Some(inner), "b");
+  assert_eq!(arena.len(), 4);
+  assert_eq!(arena.children(root), &[a, inner]);
+  assert_eq!(arena.ancestors(b).collect::<Vec<_>>(), vec![inner, root]);
+  assert_eq!(arena.leaf(b), Some("b"));
+  assert!(!arena.is_leaf(inner));
+  assert_eq!(b.index(), 3);
+  assert_eq!(arena.node_to_cirru(inner), Cirru::from(vec!["b"]));
+  assert_eq!(Vec::<Cirru>::from(&arena), parse("a (b)").unwrap());
+}
+
+#[test]
+fn arena_deep_nesting() {
+  let mut arena = CirruArena::new();
+  let mut parent = None;
+  for _ in 0..200_000 {
+    parent = Some(arena.push_list(parent));
+  }
+  let leaf = arena.push_leaf(parent, "x");
+  assert_eq!(arena.ancestors(leaf).count(), 200_000);
+  drop(arena);
+}
@@ -0,0 +1,66 @@
+extern crate cirru_parser;
+
+mod common;
+
+use std::borrow::Cow;
+
+use cirru_parser::{CirruRef, parse, parse_borrowed};
+
+/// checks that every leaf without escapes points into `code`
+fn check_borrowed(code: &str, xs: &[CirruRef]) {
+  let range = code.as_ptr() as usize..code.as_ptr() as usize + code.len();
+  for x in xs {
+    match x {
+      CirruRef::Leaf(Cow::Borrowed(s)) => assert!(range.contains(&(s.as_ptr() as usize)) || s.is_empty()),
+      CirruRef::Leaf(Cow::Owned(s)) => assert!(code.contains('\\'), "owned leaf {s:?}"),
+      CirruRef::List(ys) => check_borrowed(code, ys),
+    }
+  }
+}
+
+#[test]
+fn borrowed_leaves_on_files() -> Result<(), String> {
+  for (_, code) in common::fixture_files(&["./tests/cirru", "./tests/writer_cirru"]) {
+    check_borrowed(&code, &parse_borrowed(&code)?);
+  }
+  Ok(())
+}
+
+#[test]
+fn borrowed_escapes() -> Result<(), String> {
+  let code = "a \"b c\" \"d\\\"e\" \"\"";
+  let tree = parse_borrowed(code)?;
+  assert_eq!(
+    tree,
+    vec![CirruRef::List(vec![
+      CirruRef::Leaf(Cow::Borrowed("a")),
+      CirruRef::Leaf(Cow::Borrowed("b c")),
+      CirruRef::Leaf(Cow::Owned("d\"e".to_owned())),
+      CirruRef::Leaf(Cow::Borrowed("")),
+    ])]
+  );
+  assert_eq!(tree[0], parse(code)?[0]);
+  assert_eq!(tree[0].clone().into_owned(), CirruRef::from(&parse(code)?[0]));
+  Ok(())
+}
+
+#[test]
+fn borrowed_errors_match_parse() {
+  for code in [
+    "a (b",
+    "a) b",
+    "a\n   b",
+    "a \"b",
+    "a \"b\\x\"",
+    "a ((b)\n  c\n)d",
+    "a) b\nc \"d",
+    "a (b\nc) d\ne",
+    "",
+    "\n\n",
+    "  a\n b\nc",
+  ] {
+    let expected = parse(code).map(|xs| xs.into_iter().map(|x| CirruRef::from(&x).into_owned()).collect::<Vec<_>>());
+    let got = parse_borrowed(code).map(|xs| xs.into_iter().map(CirruRef::into_owned).collect::<Vec<_>>());
+    assert_eq!(got, expected, "parsing {code:?}");
+  }
+}
@@ -0,0 +1,56 @@
+extern crate cirru_parser;
+
+use cirru_parser::{
+  CirruWriterOptions, CommentPolicy, CommentedNode, ParseOptions, format_with_comments, parse, parse_with_comments, parse_with_options,
+};
+
+const CODE: &str = "; header\ndefn f (x)\n  ; add one\n  + x 1\n  ;; end of body\n\n;; footer\n";
+
+#[test]
+fn strip_comments() -> Result<(), String> {
+  let options = ParseOptions {
+    comments: CommentPolicy::Strip,
+    ..Default::default()
+  };
+  assert_eq!(parse_with_options(CODE, &options)?, parse("defn f (x)\n  + x 1")?);
+  assert_eq!(parse_with_options("a (; b) c $ ; d", &options)?, parse("a c")?);
+  // kept by default
+  assert_eq!(parse_with_options(CODE, &ParseOptions::default())?, parse(CODE)?);
+  Ok(())
+}
+
+#[test]
+fn attach_comments() -> Result<(), String> {
+  let tree = parse_with_comments(CODE, &ParseOptions::default())?;
+  assert_eq!(tree.items.len(), 1);
+  assert_eq!(tree.items[0].comments[0].text(), "header");
+  assert_eq!(tree.trailing.len(), 1);
+  assert_eq!(&*tree.trailing[0].marker, ";;");
+  assert_eq!(tree.trailing[0].text(), "footer");
+
+  let CommentedNode::List(defn) = &tree.items[0].node else {
+    panic!("expected a list");
+  };
+  assert_eq!(defn.items.len(), 4);
+  assert_eq!(defn.items[3].comments[0].text(), "add one");
+  assert_eq!(defn.trailing[0].text(), "end of body");
+
+  assert_eq!(tree.to_cirru(), parse("defn f (x)\n  + x 1")?);
+  assert_eq!(tree.to_cirru_with_comments(), parse(CODE)?);
+  Ok(())
+}
+
+#[test]
+fn write_comments_back() -> Result<(), String> {
+  for code in [CODE, "a (; b) c", "a\n  b\n  ; c", "; only"] {
+    let tree = parse_with_comments(code, &ParseOptions::default())?;
+    let written = format_with_comments(&tree, CirruWriterOptions::default())?;
+    assert_eq!(parse_with_comments(&written, &ParseOptions::default())?, tree, "writing {code:?}");
+  }
+  let tree = parse_with_comments(CODE, &ParseOptions::default())?;
+  assert_eq!(
+    format_with_comments(&tree, CirruWriterOptions::default())?,
+    "\n; header\n\ndefn f (x)\n  ; add one\n  + x 1\n  ;; end of body\n\n;; footer\n"
+  );
+  Ok(())
+}
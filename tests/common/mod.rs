@@ -0,0 +1,33 @@
+//! helpers shared by the integration tests, each test crate uses a part of them
+#![allow(dead_code)]
+
+use std::path::PathBuf;
+
+/// small deterministic generator, so failures can be reproduced
+pub struct Random(pub u64);
+
+impl Random {
+  /// a number below `n`, or 0 when `n` is 0
+  pub fn below(&mut self, n: usize) -> usize {
+    self.0 = self.0.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+    ((self.0 >> 33) as usize) % n.max(1)
+  }
+}
+
+/// paths and code of the files in `dirs`
+pub fn fixture_files(dirs: &[&str]) -> Vec<(PathBuf, String)> {
+  let mut files = vec![];
+  for dir in dirs {
+    for entry in std::fs::read_dir(dir).expect("reading fixture dir") {
+      let path = entry.expect("reading fixture entry").path();
+      let code = std::fs::read_to_string(&path).expect("reading fixture");
+      files.push((path, code));
+    }
+  }
+  files
+}
+
+/// the files of code in `tests/cirru`
+pub fn cirru_files() -> Vec<(PathBuf, String)> {
+  fixture_files(&["./tests/cirru"])
+}
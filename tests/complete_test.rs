@@ -0,0 +1,58 @@
+extern crate cirru_parser;
+
+use cirru_parser::{CirruErrorKind, InputStatus, check_input_complete};
+
+fn incomplete(expected_closers: usize, open_string: bool) -> InputStatus {
+  InputStatus::Incomplete {
+    expected_closers,
+    open_string,
+  }
+}
+
+#[test]
+fn complete_input() {
+  for code in [
+    "",
+    "a",
+    "defn f (x)\n  + x 1\n",
+    "a $ b , c",
+    "a \"b\\\"c\"",
+    "\n\n",
+    ";)(",
+    "a\n;)(\nb",
+  ] {
+    assert_eq!(check_input_complete(code), InputStatus::Complete, "checking {code:?}");
+  }
+}
+
+#[test]
+fn incomplete_input() {
+  assert_eq!(check_input_complete("a (b"), incomplete(1, false));
+  assert_eq!(check_input_complete("a (b (c\n  d"), incomplete(2, false));
+  assert_eq!(check_input_complete("a \"b"), incomplete(0, true));
+  assert_eq!(check_input_complete("a (b \"c\\"), incomplete(1, true));
+  assert_eq!(check_input_complete("a \"\\u{1"), incomplete(0, true));
+  assert_eq!(check_input_complete("a (\"b"), incomplete(1, true));
+}
+
+#[test]
+fn invalid_input() {
+  let cases = [
+    ("a)", CirruErrorKind::UnexpectedCloseParen),
+    ("a (b))", CirruErrorKind::UnexpectedCloseParen),
+    ("a\n)", CirruErrorKind::UnexpectedCloseParen),
+    ("; a)", CirruErrorKind::UnexpectedCloseParen),
+    ("a \"b\nc\"", CirruErrorKind::UnexpectedNewlineInString),
+    ("a \"\\q", CirruErrorKind::InvalidEscape('q')),
+    ("a\n   b", CirruErrorKind::InvalidIndentation(3)),
+  ];
+  for (code, kind) in cases {
+    match check_input_complete(code) {
+      InputStatus::Invalid(error) => {
+        assert_eq!(error.kind, kind, "checking {code:?}");
+        assert!(error.context.is_some(), "checking {code:?}");
+      }
+      status => panic!("expected {code:?} to be invalid, got {status:?}"),
+    }
+  }
+}
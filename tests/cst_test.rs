@@ -0,0 +1,50 @@
+extern crate cirru_parser;
+
+mod common;
+
+use cirru_parser::{CstLeafStyle, CstNode, parse, parse_cst};
+
+#[test]
+fn cst_round_trip_files() -> Result<(), String> {
+  for (path, code) in common::fixture_files(&["./tests/cirru", "./tests/writer_cirru"]) {
+    assert_eq!(parse_cst(&code)?.to_source(), code, "printing {path:?}");
+  }
+  Ok(())
+}
+
+#[test]
+fn cst_keeps_trivia_and_markers() -> Result<(), String> {
+  let code = "\n; comment line\na $ b \"c d\"   \n\n  , e (f)\n  ";
+  let cst = parse_cst(code)?;
+  assert_eq!(cst.to_source(), code);
+  assert_eq!(format!("{cst}"), code);
+  assert_eq!(cst.lower(), parse(code)?);
+
+  let mut leaves = vec![];
+  let mut markers = 0;
+  let mut parens = 0;
+  let mut stack: Vec<&CstNode> = cst.nodes.iter().collect();
+  while let Some(node) = stack.pop() {
+    match node {
+      CstNode::Leaf { raw, style, .. } => leaves.push((raw.as_str(), *style)),
+      CstNode::Expr(xs) => stack.extend(xs),
+      CstNode::Open | CstNode::Close => parens += 1,
+      CstNode::Trivia(_) => (),
+    }
+    if node.is_sugar_marker() {
+      markers += 1;
+    }
+  }
+  assert!(leaves.contains(&("\"c d\"", CstLeafStyle::Quoted)));
+  assert!(leaves.contains(&(";", CstLeafStyle::Bare)));
+  assert_eq!(markers, 2);
+  assert_eq!(parens, 2);
+  Ok(())
+}
+
+#[test]
+fn cst_errors() {
+  assert_eq!(parse_cst("a (b").unwrap_err().kind, parse("a (b").unwrap_err().kind);
+  assert_eq!(parse_cst("a) b").unwrap_err().kind, parse("a) b").unwrap_err().kind);
+  assert!(parse_cst("a\n   b").is_err());
+}
@@ -0,0 +1,165 @@
+extern crate cirru_parser;
+
+use std::thread;
+
+use cirru_parser::{
+  Cirru, CirruErrorKind, CirruWriterOptions, CommentPolicy, CommentedList, LeafInterner, MAX_NESTING_DEPTH, ParseOptions, build_exprs,
+  format, format_to_lisp, lex, parse, parse_borrowed, parse_cst, parse_recovering, parse_with_options, parse_with_spans,
+  resolve_indentations,
+};
+
+fn too_deep() -> CirruErrorKind {
+  CirruErrorKind::NestingTooDeep {
+    depth: MAX_NESTING_DEPTH + 1,
+    max: MAX_NESTING_DEPTH,
+  }
+}
+
+/// a tree of `depth` lists like `(f (f (f x)))`, with the inner list at `idx` of each list
+fn nested(depth: usize, idx: usize) -> Cirru {
+  let mut x = Cirru::List(vec!["a".into(), "b".into()]);
+  for _ in 1..depth {
+    let mut xs: Vec<Cirru> = vec!["f".into(), "g".into()];
+    xs.insert(idx, x);
+    x = Cirru::List(xs);
+  }
+  x
+}
+
+/// drops a deep tree one level at a time
+fn dismantle(x: Cirru) {
+  let mut xs = vec![x];
+  while let Some(x) = xs.pop() {
+    if let Cirru::List(ys) = x {
+      xs.extend(ys);
+    }
+  }
+}
+
+/// runs `f` on a thread with a small stack, which recursing once per level would overflow
+fn on_small_stack<T: Send>(f: impl FnOnce() -> T + Send) -> T {
+  thread::scope(|scope| {
+    thread::Builder::new()
+      .stack_size(1 << 17)
+      .spawn_scoped(scope, f)
+      .unwrap()
+      .join()
+      .unwrap()
+  })
+}
+
+#[test]
+fn parens_too_deep() {
+  let code = format!("a {}{}", "(".repeat(MAX_NESTING_DEPTH), ")".repeat(MAX_NESTING_DEPTH));
+  let error = parse(&code).unwrap_err();
+  assert_eq!(error.kind, too_deep());
+  // the line itself is the first level
+  assert_eq!(error.context.unwrap().pos.column, MAX_NESTING_DEPTH + 2);
+
+  let steps = build_exprs(&resolve_indentations(&lex(&code).unwrap())).unwrap_err();
+  assert_eq!(steps.kind, too_deep());
+  assert_eq!(parse_borrowed(&code).unwrap_err().kind, too_deep());
+  assert_eq!(parse_with_spans(&code).unwrap_err().kind, too_deep());
+  assert_eq!(parse_cst(&code).unwrap_err().kind, too_deep());
+
+  let code = format!("a {}{}", "(".repeat(MAX_NESTING_DEPTH - 1), ")".repeat(MAX_NESTING_DEPTH - 1));
+  let tree = parse(&code).unwrap();
+  assert_eq!(parse_with_spans(&code).unwrap().len(), 1);
+  dismantle(tree.into_iter().next().unwrap());
+}
+
+#[test]
+fn dollars_too_deep() {
+  let code = format!("a{}", " $ b".repeat(MAX_NESTING_DEPTH));
+  let error = parse(&code).unwrap_err();
+  assert_eq!(error.kind, too_deep());
+  assert_eq!(error.context.unwrap().pos.column, 3 + 4 * (MAX_NESTING_DEPTH - 1));
+
+  assert!(parse(&format!("a{}", " $ b".repeat(MAX_NESTING_DEPTH - 1))).is_ok());
+
+  // without `DollarSugar`, `$` is a plain leaf in a flat list
+  let options = ParseOptions {
+    resolvers: vec![],
+    ..Default::default()
+  };
+  let tree = parse_with_options(&format!("row{}", " $".repeat(MAX_NESTING_DEPTH)), &options).unwrap();
+  assert_eq!(tree[0].len(), MAX_NESTING_DEPTH + 1);
+}
+
+#[test]
+fn indentation_too_deep() {
+  let code: String = (0..=MAX_NESTING_DEPTH).map(|i| format!("{}a\n", "  ".repeat(i))).collect();
+  let error = parse(&code).unwrap_err();
+  assert_eq!(error.kind, too_deep());
+  assert_eq!(error.context.unwrap().pos.line, MAX_NESTING_DEPTH + 1);
+}
+
+#[test]
+fn recovering_flattens_deep_lists() {
+  let code = format!("a {}b{}\nc", "(".repeat(MAX_NESTING_DEPTH + 1), ")".repeat(MAX_NESTING_DEPTH + 1));
+  let (tree, errors) = parse_recovering(&code);
+  assert_eq!(errors.len(), 2);
+  assert!(errors.iter().all(|e| e.kind == too_deep()));
+  assert_eq!(tree.len(), 2);
+  assert_eq!(tree[1], Cirru::List(vec!["c".into()]));
+}
+
+#[test]
+fn writing_deep_trees() {
+  for idx in [0, 1, 2] {
+    let tree = vec![nested(600, idx)];
+    let code = on_small_stack(|| format(&tree, CirruWriterOptions::from(false)).unwrap());
+    let inline_code = on_small_stack(|| format(&tree, CirruWriterOptions::from(true)).unwrap());
+    let one_liner = on_small_stack(|| tree[0].format_one_liner().unwrap());
+    let lisp = on_small_stack(|| format_to_lisp(&tree).unwrap());
+    assert_eq!(lisp.matches('(').count(), 600);
+
+    for code in [code, inline_code, one_liner] {
+      let parsed = parse(&code).unwrap();
+      assert!(parsed == tree, "writing a tree nested at {idx}");
+      parsed.into_iter().for_each(dismantle);
+    }
+    tree.into_iter().for_each(dismantle);
+  }
+}
+
+#[test]
+fn converting_deep_trees() {
+  let mut tree = Cirru::List(vec!["a".into()]);
+  let mut plain = tree.to_owned();
+  for _ in 1..400 {
+    tree = Cirru::List(vec![vec![";", "note"].into(), "f".into(), tree]);
+    plain = Cirru::List(vec!["f".into(), plain]);
+  }
+  let (tree, plain) = (vec![tree], vec![plain]);
+  let strip = ParseOptions {
+    comments: CommentPolicy::Strip,
+    ..Default::default()
+  };
+
+  let (commented, with_comments, without, stripped) = on_small_stack(|| {
+    let commented = CommentedList::from_cirru(&tree);
+    let with_comments = commented.to_cirru_with_comments();
+    let without = commented.to_cirru();
+    let code = format(&tree, CirruWriterOptions::from(false)).unwrap();
+    let stripped = parse_with_options(&code, &strip).unwrap();
+    (commented, with_comments, without, stripped)
+  });
+  assert!(with_comments == tree);
+  assert!(without == plain);
+  assert!(stripped == plain);
+  drop(commented);
+  [with_comments, without, stripped, tree, plain]
+    .into_iter()
+    .flatten()
+    .for_each(dismantle);
+}
+
+#[test]
+fn interning_deep_trees() {
+  let tree = nested(600, 2);
+  let interned = on_small_stack(|| LeafInterner::new().intern_tree(&tree));
+  assert!(interned == tree);
+  dismantle(interned);
+  dismantle(tree);
+}
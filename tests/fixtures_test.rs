@@ -0,0 +1,58 @@
+extern crate cirru_parser;
+
+mod common;
+
+use cirru_parser::{
+  Cirru, CirruArena, CirruDocument, CirruReader, LeafInterner, ParseOptions, build_exprs, lex, parse, parse_borrowed, parse_cst,
+  parse_recovering, parse_with_interner, parse_with_options, parse_with_spans, resolve_comma, resolve_dollar, resolve_indentations,
+};
+
+#[cfg(feature = "parallel")]
+use cirru_parser::parse_parallel_with_threads;
+
+/// Every other way of parsing gives the same tree as `parse` on the test files,
+/// so the tests of each one only check what it does on its own.
+#[test]
+fn same_tree_as_parse() -> Result<(), String> {
+  for (path, code) in common::fixture_files(&["./tests/cirru", "./tests/writer_cirru"]) {
+    let expected = parse(&code)?;
+
+    let spanned: Vec<Cirru> = parse_with_spans(&code)?.into_iter().map(Cirru::from).collect();
+    assert_eq!(spanned, expected, "parse_with_spans on {path:?}");
+    assert_eq!(parse_cst(&code)?.lower(), expected, "parse_cst on {path:?}");
+    assert_eq!(
+      parse_recovering(&code),
+      (expected.to_owned(), vec![]),
+      "parse_recovering on {path:?}"
+    );
+    assert_eq!(CirruDocument::new(code.as_str()).tree()?, expected, "CirruDocument on {path:?}");
+    let borrowed: Vec<Cirru> = parse_borrowed(&code)?.into_iter().map(Cirru::from).collect();
+    assert_eq!(borrowed, expected, "parse_borrowed on {path:?}");
+    assert_eq!(
+      parse_with_interner(&code, &mut LeafInterner::new())?,
+      expected,
+      "parse_with_interner on {path:?}"
+    );
+    assert_eq!(CirruArena::from_cirru(&expected).to_cirru(), expected, "CirruArena on {path:?}");
+    assert_eq!(
+      parse_with_options(&code, &ParseOptions::default())?,
+      expected,
+      "parse_with_options on {path:?}"
+    );
+    let mut steps = build_exprs(&resolve_indentations(&lex(&code)?))?;
+    resolve_dollar(&mut steps);
+    resolve_comma(&mut steps);
+    assert_eq!(steps, expected, "parse steps on {path:?}");
+    let streamed = CirruReader::new(code.as_bytes()).collect::<Result<Vec<_>, _>>()?;
+    assert_eq!(streamed, expected, "CirruReader on {path:?}");
+    #[cfg(feature = "parallel")]
+    for threads in [1, 3] {
+      assert_eq!(
+        parse_parallel_with_threads(&code, threads)?,
+        expected,
+        "parse_parallel_with_threads on {path:?}"
+      );
+    }
+  }
+  Ok(())
+}
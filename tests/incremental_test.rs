@@ -0,0 +1,90 @@
+extern crate cirru_parser;
+
+mod common;
+
+use cirru_parser::{CirruDocument, TextEdit, parse};
+
+use common::Random;
+
+fn char_boundary(code: &str, mut idx: usize) -> usize {
+  while !code.is_char_boundary(idx) {
+    idx -= 1;
+  }
+  idx
+}
+
+#[test]
+fn edits_match_fresh_parse() -> Result<(), String> {
+  let pieces = ["a", " ", "\n", "\n  ", "(", ")", "$", ",", "\"", "\\", "x y", "\n\n", "   "];
+  let mut random = Random(7);
+  for (path, code) in common::cirru_files() {
+    let mut doc = CirruDocument::new(code.as_str());
+    for _ in 0..60 {
+      let len = doc.code().len();
+      let start = char_boundary(doc.code(), random.below(len + 1));
+      let end = char_boundary(doc.code(), (start + random.below(6)).min(len));
+      let text = pieces[random.below(pieces.len())];
+      let edit = TextEdit::new(start..end, text);
+
+      let mut expected = doc.code().to_owned();
+      expected.replace_range(start..end, text);
+      let tree = doc.apply_edit(&edit);
+      assert_eq!(doc.code(), expected);
+      assert_eq!(tree, parse(&expected), "after {edit:?} in {path:?}");
+    }
+  }
+  Ok(())
+}
+
+#[test]
+fn edits_reuse_other_expressions() -> Result<(), String> {
+  let code = "defn a ()\n  b c\n\ndefn d ()\n  e f\n\ndefn g ()\n  h i\n";
+  let mut doc = CirruDocument::new(code);
+
+  // editing in the middle expression only reparses it
+  let pos = code.find("e f").expect("found");
+  let tree = doc.apply_edit(&TextEdit::new(pos..pos, "e"))?;
+  assert_eq!(doc.last_reparsed(), 17..35);
+  assert_eq!(tree, parse(doc.code())?);
+
+  // an open parenthesis may be closed by later lines, so the rest is parsed too
+  let pos = doc.code().find("ee f").expect("found");
+  assert!(doc.apply_edit(&TextEdit::new(pos..pos, "(")).is_err());
+  assert_eq!(doc.last_reparsed(), 17..doc.code().len());
+
+  // closing it on the next top-level line joins two expressions
+  let pos = doc.code().find("h i").expect("found");
+  let tree = doc.apply_edit(&TextEdit::new(pos + 3..pos + 3, ")"))?;
+  assert_eq!(tree, parse(doc.code())?);
+  assert_eq!(tree.len(), 2);
+
+  assert!(doc.apply_edit(&TextEdit::new(3..1000, "")).is_err());
+  Ok(())
+}
+
+#[test]
+fn errors_same_as_parse() -> Result<(), String> {
+  let deep = format!("{}{}", "(".repeat(1001), ")".repeat(1001));
+  let code = format!("a\n\nb {deep}\nc\n");
+  let doc = CirruDocument::new(code.as_str());
+  assert_eq!(doc.tree(), parse(&code));
+  assert!(doc.tree().is_err_and(|e| e.context.is_some_and(|ctx| ctx.pos.line == 3)));
+
+  // nesting made too deep by an edit
+  let mut doc = CirruDocument::new("a\n\nb\nc\n");
+  let tree = doc.apply_edit(&TextEdit::new(4..4, format!(" {deep}")));
+  assert_eq!(tree, parse(doc.code()));
+  assert!(tree.is_err());
+  Ok(())
+}
+
+#[test]
+fn whitespace_at_line_start() -> Result<(), String> {
+  for code in ["a\n\x0c\x0cb\n", "a\n\x0bb\n  c\n\u{a0}d\n"] {
+    let mut doc = CirruDocument::new(code);
+    assert_eq!(doc.tree(), parse(code), "parsing {code:?}");
+    let tree = doc.apply_edit(&TextEdit::new(0..1, "e"))?;
+    assert_eq!(tree, parse(doc.code())?, "parsing {:?}", doc.code());
+  }
+  Ok(())
+}
@@ -52,3 +52,44 @@ fn handle_indentation() -> Result<(), String> {
   );
   Ok(())
 }
+
+#[test]
+fn deep_indentation() -> Result<(), String> {
+  use cirru_parser::{Cirru, CirruWriterOptions, format, parse, parse_borrowed};
+
+  // one more level on each line, far beyond 255 spaces
+  let depth = 300;
+  let code: String = (0..depth).map(|i| format!("{}a{i}\n", "  ".repeat(i))).collect();
+  let tokens = lex(&code).map_err(|e| e.to_string())?;
+  assert_eq!(tokens[tokens.len() - 2], CirruLexItem::Indent(depth - 1));
+
+  let mut expected = Cirru::List(vec![Cirru::leaf(format!("a{}", depth - 1))]);
+  for i in (0..depth - 1).rev() {
+    expected = Cirru::List(vec![Cirru::leaf(format!("a{i}")), expected]);
+  }
+  let tree = parse(&code)?;
+  assert_eq!(tree, vec![expected]);
+  assert_eq!(parse_borrowed(&code)?.iter().map(|x| x.to_cirru()).collect::<Vec<_>>(), tree);
+
+  let written = format(&tree, CirruWriterOptions::from(false))?;
+  assert_eq!(parse(&written)?, tree);
+  Ok(())
+}
+
+#[test]
+fn indentation_limit() {
+  use cirru_parser::{CirruErrorKind, Lexer};
+
+  let code = "a\n  b\n    c\n      d";
+  let error = Lexer::new(code).with_max_indent(2).find_map(|x| x.err()).expect("too deep");
+  assert_eq!(error.kind, CirruErrorKind::IndentationTooDeep { depth: 3, max: 2 });
+  assert_eq!(error.context.map(|c| c.pos.line), Some(4));
+
+  let levels: Vec<CirruLexItem> = Lexer::recovering(code)
+    .with_max_indent(1)
+    .filter_map(|x| x.ok())
+    .map(|(item, _)| item)
+    .filter(|item| matches!(item, CirruLexItem::Indent(_)))
+    .collect();
+  assert_eq!(levels, vec![0.into(), 1.into(), 1.into(), 1.into()]);
+}
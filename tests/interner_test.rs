@@ -0,0 +1,50 @@
+extern crate cirru_parser;
+
+mod common;
+
+use std::sync::Arc;
+
+use cirru_parser::{Cirru, LeafInterner, parse, parse_with_interner};
+
+fn collect_leaves<'a>(xs: &'a [Cirru], acc: &mut Vec<&'a Arc<str>>) {
+  for x in xs {
+    match x {
+      Cirru::Leaf(s) => acc.push(s),
+      Cirru::List(ys) => collect_leaves(ys, acc),
+    }
+  }
+}
+
+#[test]
+fn interned_leaves_shared_across_files() -> Result<(), String> {
+  let mut interner = LeafInterner::new();
+  let mut trees = vec![];
+  for (_, code) in common::cirru_files() {
+    trees.push(parse_with_interner(&code, &mut interner)?);
+  }
+
+  // leaves of the same text across all files share one allocation
+  let mut leaves = vec![];
+  for tree in &trees {
+    collect_leaves(tree, &mut leaves);
+  }
+  for leaf in &leaves {
+    assert!(Arc::ptr_eq(leaf, &interner.intern(leaf)));
+  }
+  assert!(interner.len() < leaves.len());
+  Ok(())
+}
+
+#[test]
+fn interner_errors_and_shrink() {
+  let mut interner = LeafInterner::new();
+  assert_eq!(parse_with_interner("a (b", &mut interner), parse("a (b"));
+  assert_eq!(parse_with_interner("a \"b\\x\"", &mut interner), parse("a \"b\\x\""));
+
+  let tree = parse_with_interner("a b $ c", &mut interner).unwrap();
+  let copy = interner.intern_tree(&parse("c d").unwrap()[0]);
+  drop(tree);
+  interner.shrink();
+  assert_eq!(interner.len(), 2);
+  assert_eq!(copy, Cirru::from(vec!["c", "d"]));
+}
@@ -48,9 +48,95 @@ fn lex_strings() -> Result<(), String> {
 
 #[test]
 fn escape_chars() -> Result<(), String> {
-  // Unicode escape should now fail with error
-  assert!(lex(r#""\u{6c49}""#).is_err());
+  assert_eq!(
+    lex(r#""\u{6c49}\u{1F600}a""#).map_err(|e| e.to_string())?,
+    vec![0.into(), "汉😀a".into()]
+  );
 
   assert_eq!(lex(r#""\'""#).map_err(|e| e.to_string())?, vec![0.into(), r#"'"#.into()]);
   Ok(())
 }
+
+#[test]
+fn lexer_iterator() -> Result<(), String> {
+  use cirru_parser::Lexer;
+
+  let tokens: Vec<_> = Lexer::new("a (b)\n  \"c\"").collect::<Result<_, _>>().map_err(|e| e.to_string())?;
+  let items: Vec<CirruLexItem> = tokens.iter().map(|(item, _)| item.to_owned()).collect();
+  assert_eq!(items, lex("a (b)\n  \"c\"").map_err(|e| e.to_string())?);
+
+  let spans: Vec<(usize, usize)> = tokens.iter().map(|(_, s)| (s.start.offset, s.end.offset)).collect();
+  assert_eq!(spans, vec![(0, 0), (0, 1), (2, 3), (3, 4), (4, 5), (6, 8), (8, 11)]);
+
+  // only scans as far as needed, errors later in the code are not reached
+  let mut lexer = Lexer::new("a b\n   c");
+  let first: Vec<_> = lexer.by_ref().take(3).collect::<Result<_, _>>().map_err(|e| e.to_string())?;
+  assert_eq!(first.len(), 3);
+  assert!(lexer.next().expect("error at line 2").is_err());
+  assert!(lexer.next().is_none());
+  Ok(())
+}
+
+#[test]
+fn lexer_positions_in_runs() -> Result<(), String> {
+  use cirru_parser::Lexer;
+
+  // runs of text are scanned at once, columns still count characters
+  let code = "ab   \"中文 é\\\\x\" cd\u{3000}e\n    f中g";
+  let tokens: Vec<_> = Lexer::new(code).collect::<Result<_, _>>().map_err(|e| e.to_string())?;
+  let items: Vec<CirruLexItem> = tokens.iter().map(|(item, _)| item.to_owned()).collect();
+  assert_eq!(
+    items,
+    vec![
+      0.into(),
+      "ab".into(),
+      "中文 é\\x".into(),
+      "cd\u{3000}e".into(),
+      2.into(),
+      "f中g".into()
+    ]
+  );
+  let positions: Vec<(usize, usize, usize)> = tokens.iter().map(|(_, s)| (s.start.line, s.start.column, s.end.column)).collect();
+  assert_eq!(positions, vec![(1, 1, 1), (1, 1, 3), (1, 6, 15), (1, 16, 20), (2, 1, 5), (2, 5, 8)]);
+  Ok(())
+}
+
+#[test]
+fn lexer_recovering() {
+  use cirru_parser::Lexer;
+
+  let items: Vec<_> = Lexer::recovering("a \"b\n   c \"\\x\"").collect();
+  assert_eq!(items.iter().filter(|x| x.is_err()).count(), 3);
+  let tokens: Vec<CirruLexItem> = items.into_iter().filter_map(|x| x.ok()).map(|(item, _)| item).collect();
+  assert_eq!(tokens, vec![0.into(), "a".into(), "b".into(), 1.into(), "c".into(), "x".into()]);
+}
+
+#[test]
+fn unicode_escape_errors() {
+  use cirru_parser::CirruErrorKind;
+
+  let cases = [
+    (r#""\u6c49""#, "\\u", 3),
+    (r#""\u{}""#, "\\u{", 4),
+    (r#""\u{12g}""#, "\\u{12", 6),
+    (r#""\u{1234567}""#, "\\u{123456", 10),
+    (r#""\u{d800}""#, "\\u{d800", 8),
+    (r#""\u{110000}""#, "\\u{110000", 10),
+    (r#""\u{12"#, "\\u{12", 6),
+  ];
+  for (code, text, column) in cases {
+    let error = lex(code).unwrap_err();
+    assert_eq!(error.kind, CirruErrorKind::InvalidUnicodeEscape(text.to_owned()), "lexing {code}");
+    assert_eq!(error.context.map(|c| c.pos.offset), Some(column), "lexing {code}");
+  }
+}
+
+#[test]
+fn unicode_escape_recovering() {
+  use cirru_parser::Lexer;
+
+  let items: Vec<_> = Lexer::recovering(r#"a "\u{zz}" "\u{1F600"b"#).collect();
+  assert_eq!(items.iter().filter(|x| x.is_err()).count(), 2);
+  let tokens: Vec<CirruLexItem> = items.into_iter().filter_map(|x| x.ok()).map(|(item, _)| item).collect();
+  assert_eq!(tokens, vec![0.into(), "a".into(), "\\u{zz}".into(), "\\u{1F600".into(), "b".into()]);
+}
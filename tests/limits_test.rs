@@ -0,0 +1,197 @@
+extern crate cirru_parser;
+
+use cirru_parser::{
+  CirruError, CirruErrorKind, Lexer, ParseLimits, ParseOptions, SourcePos, build_exprs_with_limits, lex, lex_with_limits, parse,
+  parse_with_options, resolve_indentations, resolve_indentations_with_limits,
+};
+
+fn parse_limited(code: &str, limits: ParseLimits) -> Result<Vec<cirru_parser::Cirru>, CirruError> {
+  let options = ParseOptions {
+    limits,
+    ..Default::default()
+  };
+  parse_with_options(code, &options)
+}
+
+fn pos_of(error: CirruError) -> SourcePos {
+  error.context.expect("limit errors carry a position").pos
+}
+
+#[test]
+fn input_size() {
+  let limits = ParseLimits {
+    max_input_size: Some(5),
+    ..Default::default()
+  };
+  assert!(parse_limited("a b\nc", limits).is_ok());
+  let error = parse_limited("a b\ncd", limits).unwrap_err();
+  assert_eq!(error.kind, CirruErrorKind::InputTooLarge { size: 6, max: 5 });
+  assert_eq!(pos_of(error), SourcePos::new(2, 2, 5));
+
+  // the position is at a character boundary
+  let error = parse_limited("a \"ééé\"", limits).unwrap_err();
+  assert_eq!(pos_of(error), SourcePos::new(1, 5, 5));
+}
+
+#[test]
+fn nesting_depth() {
+  let limits = ParseLimits {
+    max_depth: 2,
+    ..Default::default()
+  };
+  assert!(parse_limited("a (b)\n  c", limits).is_ok());
+
+  let error = parse_limited("a (b (c))", limits).unwrap_err();
+  assert_eq!(error.kind, CirruErrorKind::NestingTooDeep { depth: 3, max: 2 });
+  assert_eq!(pos_of(error).column, 6);
+
+  let error = parse_limited("a\n  b\n    c", limits).unwrap_err();
+  assert_eq!(pos_of(error).line, 3);
+
+  let error = parse_limited("a $ b $ c", limits).unwrap_err();
+  assert_eq!(pos_of(error).column, 7);
+}
+
+#[test]
+fn leaf_length() {
+  let limits = ParseLimits {
+    max_leaf_len: Some(3),
+    ..Default::default()
+  };
+  assert!(parse_limited("abc \"d\\ne\"", limits).is_ok());
+
+  let error = parse_limited("a\n  abcd", limits).unwrap_err();
+  assert_eq!(error.kind, CirruErrorKind::LeafTooLong { len: 4, max: 3 });
+  assert_eq!(pos_of(error), SourcePos::new(2, 3, 4));
+
+  // counted after escapes
+  let error = parse_limited("a \"\\u{1F600}\"", limits).unwrap_err();
+  assert_eq!(error.kind, CirruErrorKind::LeafTooLong { len: 4, max: 3 });
+}
+
+#[test]
+fn token_count() {
+  let limits = ParseLimits {
+    max_tokens: Some(3),
+    ..Default::default()
+  };
+  // the line start is a token
+  assert!(parse_limited("a b", limits).is_ok());
+  let error = parse_limited("a b c d", limits).unwrap_err();
+  assert_eq!(error.kind, CirruErrorKind::TooManyTokens { max: 3 });
+  assert_eq!(pos_of(error).column, 5);
+}
+
+#[test]
+fn top_level_count() {
+  let limits = ParseLimits {
+    max_top_level: Some(2),
+    ..Default::default()
+  };
+  assert!(parse_limited("a\n  b\nc", limits).is_ok());
+  let error = parse_limited("a\nb\n\nc (d)", limits).unwrap_err();
+  assert_eq!(error.kind, CirruErrorKind::TooManyExpressions { max: 2 });
+  assert_eq!(pos_of(error), SourcePos::new(4, 1, 5));
+}
+
+#[test]
+fn lexer_stops_at_limits() {
+  let limits = ParseLimits {
+    max_tokens: Some(2),
+    ..Default::default()
+  };
+  let items: Vec<_> = Lexer::recovering("a b \"c\nd").with_limits(limits).collect();
+  assert_eq!(items.len(), 3);
+  assert!(items[..2].iter().all(Result::is_ok));
+  assert_eq!(items[2].as_ref().unwrap_err().kind, CirruErrorKind::TooManyTokens { max: 2 });
+}
+
+#[test]
+fn input_size_checked_once() {
+  let options = ParseOptions {
+    limits: ParseLimits {
+      max_input_size: Some(3),
+      ..Default::default()
+    },
+    ..Default::default()
+  };
+  let items: Vec<_> = Lexer::recovering("abcdef")
+    .with_options(&options)
+    .with_limits(options.limits)
+    .collect();
+  assert_eq!(items.len(), 1);
+  assert!(matches!(&items[0], Err(e) if e.kind == CirruErrorKind::InputTooLarge { size: 6, max: 3 }));
+
+  // looser limits set later are the ones checked
+  let items: Vec<_> = Lexer::new("abcdef")
+    .with_options(&options)
+    .with_limits(ParseLimits::default())
+    .collect();
+  assert!(items.iter().all(Result::is_ok));
+}
+
+#[test]
+fn lexing_in_steps() {
+  let limits = ParseLimits {
+    max_leaf_len: Some(2),
+    ..Default::default()
+  };
+  assert_eq!(lex_with_limits("ab c", &limits), lex("ab c"));
+  let error = lex_with_limits("ab\n  cde", &limits).unwrap_err();
+  assert_eq!(error.kind, CirruErrorKind::LeafTooLong { len: 3, max: 2 });
+  assert_eq!(pos_of(error), SourcePos::new(2, 3, 5));
+}
+
+#[test]
+fn resolving_indentations_in_steps() {
+  let tokens = lex("a\n  b\n    c\nd").unwrap();
+  assert_eq!(
+    resolve_indentations_with_limits(&tokens, &ParseLimits::default()).unwrap(),
+    resolve_indentations(&tokens)
+  );
+
+  let limits = ParseLimits {
+    max_depth: 2,
+    ..Default::default()
+  };
+  let error = resolve_indentations_with_limits(&tokens, &limits).unwrap_err();
+  assert_eq!(error.kind, CirruErrorKind::NestingTooDeep { depth: 3, max: 2 });
+
+  let limits = ParseLimits {
+    max_top_level: Some(1),
+    ..Default::default()
+  };
+  let error = resolve_indentations_with_limits(&tokens, &limits).unwrap_err();
+  assert_eq!(error.kind, CirruErrorKind::TooManyExpressions { max: 1 });
+
+  let limits = ParseLimits {
+    max_tokens: Some(7),
+    ..Default::default()
+  };
+  let error = resolve_indentations_with_limits(&tokens, &limits).unwrap_err();
+  assert_eq!(error.kind, CirruErrorKind::TooManyTokens { max: 7 });
+}
+
+#[test]
+fn building_in_steps() {
+  let tokens = resolve_indentations(&lex("a (b)\nc").unwrap());
+  let limits = ParseLimits {
+    max_top_level: Some(1),
+    ..Default::default()
+  };
+  let error = build_exprs_with_limits(&tokens, &limits).unwrap_err();
+  assert_eq!(error.kind, CirruErrorKind::TooManyExpressions { max: 1 });
+
+  let limits = ParseLimits {
+    max_depth: 1,
+    ..Default::default()
+  };
+  let error = build_exprs_with_limits(&tokens, &limits).unwrap_err();
+  assert_eq!(error.kind, CirruErrorKind::NestingTooDeep { depth: 2, max: 1 });
+}
+
+#[test]
+fn default_limits() {
+  let code = format!("{}\n", "a bb \"ccc\" ".repeat(100)).repeat(100);
+  assert_eq!(parse_limited(&code, ParseLimits::default()), parse(&code));
+}
@@ -0,0 +1,210 @@
+extern crate cirru_parser;
+
+use cirru_parser::{CirruErrorKind, IndentUnit, MixedIndent, ParseLimits, ParseOptions, parse, parse_with_options};
+
+#[test]
+fn indent_units() -> Result<(), String> {
+  let expected = parse("a\n  b\n    c\n  d")?;
+  let tabs = ParseOptions {
+    indent: IndentUnit::Tab,
+    ..Default::default()
+  };
+  assert_eq!(parse_with_options("a\n\tb\n\t\tc\n\td", &tabs)?, expected);
+
+  let four = ParseOptions {
+    indent: IndentUnit::FourSpaces,
+    ..Default::default()
+  };
+  assert_eq!(parse_with_options("a\n    b\n        c\n    d", &four)?, expected);
+  let error = parse_with_options("a\n  b", &four).unwrap_err();
+  assert_eq!(error.kind, CirruErrorKind::InvalidIndentation(2));
+  Ok(())
+}
+
+#[test]
+fn mixed_indentation() -> Result<(), String> {
+  // tabs are rejected by default, at their position
+  let error = parse("a\n  \tb").unwrap_err();
+  assert_eq!(error.kind, CirruErrorKind::UnexpectedIndentChar('\t'));
+  assert_eq!(error.context.map(|c| (c.pos.line, c.pos.column)), Some((2, 3)));
+
+  let tabs = ParseOptions {
+    indent: IndentUnit::Tab,
+    ..Default::default()
+  };
+  let error = parse_with_options("a\n\t b", &tabs).unwrap_err();
+  assert_eq!(error.kind, CirruErrorKind::UnexpectedIndentChar(' '));
+
+  // whitespace on blank lines is not checked
+  assert_eq!(parse("a\n\t\n  b")?, parse("a\n  b")?);
+
+  let normalized = ParseOptions {
+    mixed_indent: MixedIndent::Normalize { tab_width: 2 },
+    ..Default::default()
+  };
+  assert_eq!(
+    parse_with_options("a\n\tb\n  \tc\n\t  d", &normalized)?,
+    parse("a\n  b\n    c\n    d")?
+  );
+
+  let normalized_tabs = ParseOptions {
+    indent: IndentUnit::Tab,
+    mixed_indent: MixedIndent::Normalize { tab_width: 4 },
+    ..Default::default()
+  };
+  assert_eq!(parse_with_options("a\n    b\n\t  \tc", &normalized_tabs)?, parse("a\n  b\n    c")?);
+  Ok(())
+}
+
+#[test]
+fn max_indent_option() {
+  let options = ParseOptions {
+    max_indent: Some(1),
+    ..Default::default()
+  };
+  let error = parse_with_options("a\n  b\n    c", &options).unwrap_err();
+  assert_eq!(error.kind, CirruErrorKind::IndentationTooDeep { depth: 2, max: 1 });
+
+  // the lexer checks indentation before lists are counted
+  let both = ParseOptions {
+    max_indent: Some(1),
+    limits: ParseLimits {
+      max_depth: 2,
+      ..Default::default()
+    },
+    ..Default::default()
+  };
+  let error = parse_with_options("a\n  b\n    c", &both).unwrap_err();
+  assert_eq!(error.kind, CirruErrorKind::IndentationTooDeep { depth: 2, max: 1 });
+  let error = parse_with_options("a (b (c))", &both).unwrap_err();
+  assert_eq!(error.kind, CirruErrorKind::NestingTooDeep { depth: 3, max: 2 });
+}
+
+#[test]
+fn whitespace_normalized() -> Result<(), String> {
+  use cirru_parser::{CirruDocument, TextEdit, WhitespacePolicy, parse_cst, parse_with_spans};
+
+  let crlf = "\u{feff}a b\r\n  c\t(d)\r\n \r\n\te\r\nf\tg\r\n";
+  let lf = "a b\n  c (d)\n\n  e\nf g\n";
+  let options = ParseOptions {
+    mixed_indent: MixedIndent::Normalize { tab_width: 2 },
+    ..Default::default()
+  };
+  assert_eq!(parse_with_options(crlf, &options)?, parse(lf)?);
+
+  // other whitespace stays in leaves unless asked for
+  assert_eq!(parse("a\u{a0}b cd\u{3000}e")?, vec![vec!["a\u{a0}b", "cd\u{3000}e"].into()]);
+  let all = ParseOptions {
+    whitespace: WhitespacePolicy::NormalizeAll,
+    ..Default::default()
+  };
+  assert_eq!(parse_with_options("f\u{a0}g\n\u{3000}\x0ch", &all)?, parse("f g\n  h")?);
+
+  // a lone `\r` stays in its token
+  assert_eq!(parse("a\rb c\r\n")?, parse("\"a\\rb\" c")?);
+
+  let spans = parse_with_spans("\u{feff}a\r\n  b")?;
+  let cirru_parser::SpannedCirru::List(xs, _) = &spans[0] else {
+    unreachable!()
+  };
+  assert_eq!((xs[0].span().start.column, xs[0].span().start.offset), (1, 3));
+  assert_eq!(xs[0].span().end.offset, 4);
+
+  let code = "\u{feff}a b\r\n  c\r\nd\r\n";
+  assert_eq!(parse_cst(code)?.to_source(), code);
+  let mut doc = CirruDocument::new(code);
+  let tree = doc.apply_edit(&TextEdit::new(14..15, "e"))?;
+  assert_eq!(tree, parse(doc.code())?);
+  Ok(())
+}
+
+#[test]
+fn whitespace_rejected() {
+  use cirru_parser::WhitespacePolicy;
+
+  let options = ParseOptions {
+    whitespace: WhitespacePolicy::Reject,
+    ..Default::default()
+  };
+  let cases = [
+    ("a b\r\nc", CirruErrorKind::UnexpectedWhitespace('\r'), (1, 4)),
+    ("a\tb", CirruErrorKind::UnexpectedWhitespace('\t'), (1, 2)),
+    ("a\n  b\u{3000}", CirruErrorKind::UnexpectedWhitespace('\u{3000}'), (2, 4)),
+    ("\u{feff}a", CirruErrorKind::UnexpectedChar('\u{feff}'), (1, 1)),
+  ];
+  for (code, kind, pos) in cases {
+    let error = parse_with_options(code, &options).unwrap_err();
+    assert_eq!(error.kind, kind, "parsing {code:?}");
+    assert_eq!(error.context.map(|c| (c.pos.line, c.pos.column)), Some(pos), "parsing {code:?}");
+  }
+  assert!(parse_with_options("a\rb \"c\td\"", &options).is_ok());
+}
+
+#[test]
+fn sugar_resolvers() -> Result<(), String> {
+  use std::sync::Arc;
+
+  use cirru_parser::{Cirru, CommaSugar};
+
+  let code = "a $ b c\n  , d e";
+  let raw = ParseOptions {
+    resolvers: vec![],
+    ..Default::default()
+  };
+  let tree = parse_with_options(code, &raw)?;
+  assert_eq!(
+    tree,
+    vec![Cirru::List(vec![
+      "a".into(),
+      "$".into(),
+      "b".into(),
+      "c".into(),
+      vec![",", "d", "e"].into()
+    ])]
+  );
+
+  let only_comma = ParseOptions {
+    resolvers: vec![Arc::new(CommaSugar)],
+    ..Default::default()
+  };
+  assert_eq!(
+    parse_with_options(code, &only_comma)?,
+    vec![Cirru::from(vec!["a", "$", "b", "c", "d", "e"])]
+  );
+  assert_eq!(parse_with_options(code, &ParseOptions::default())?, parse(code)?);
+
+  // a splice marker as a function, running before the built-in resolvers
+  fn splice(xs: &mut Vec<Cirru>) {
+    for x in std::mem::take(xs) {
+      match x {
+        Cirru::List(ys) if ys.first().is_some_and(|y| y.eq_leaf("...")) => xs.extend(ys.into_iter().skip(1)),
+        Cirru::List(mut ys) => {
+          splice(&mut ys);
+          xs.push(Cirru::List(ys));
+        }
+        leaf => xs.push(leaf),
+      }
+    }
+  }
+  let mut options = ParseOptions::default();
+  options.resolvers.insert(0, Arc::new(splice));
+  assert_eq!(parse_with_options("a (... b c) $ d", &options)?, parse("a b c (d)")?);
+  assert!(format!("{options:?}").contains("DollarSugar"));
+  Ok(())
+}
+
+#[test]
+fn pipeline_steps() -> Result<(), String> {
+  use cirru_parser::{Cirru, build_exprs, lex, resolve_comma, resolve_dollar, resolve_indentations};
+
+  // each step leaves the sugar of later steps as it is
+  let mut tree = build_exprs(&resolve_indentations(&lex("a $ b (, c d)")?))?;
+  let comma: Cirru = vec![",", "c", "d"].into();
+  assert_eq!(tree, vec![Cirru::List(vec!["a".into(), "$".into(), "b".into(), comma.to_owned()])]);
+  resolve_dollar(&mut tree);
+  assert_eq!(tree, vec![Cirru::List(vec!["a".into(), Cirru::List(vec!["b".into(), comma])])]);
+  resolve_comma(&mut tree);
+  assert_eq!(tree, parse("a (b c d)")?);
+  assert_eq!(build_exprs(&resolve_indentations(&lex("a (b")?)), parse("a (b"));
+  Ok(())
+}
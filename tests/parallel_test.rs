@@ -0,0 +1,49 @@
+#![cfg(feature = "parallel")]
+
+extern crate cirru_parser;
+
+mod common;
+
+use cirru_parser::{parse, parse_parallel, parse_parallel_with_threads};
+
+#[test]
+fn large_files() {
+  let big = common::cirru_files()
+    .into_iter()
+    .map(|(_, code)| code)
+    .collect::<Vec<_>>()
+    .join("\n")
+    .repeat(40);
+  assert_eq!(parse_parallel(&big), parse(&big));
+  for threads in [2, 4, 8] {
+    assert_eq!(parse_parallel_with_threads(&big, threads), parse(&big));
+  }
+}
+
+#[test]
+fn same_errors_as_parse() {
+  let cases = [
+    "a\nb (c\nd) e\nf\n",
+    "a\nb)\nc \"d\n",
+    "a (\nb\nc \"d\ne\n",
+    "a (b\nc\n   d\n",
+    "a\n)\nb (\n",
+    "a\n(b\nc\n",
+    "\u{feff}a\nb\n",
+    "a\n\x0c\x0cb\n",
+    "a\n\x0bb\n  c\n\u{a0}d\n",
+  ];
+  let deep = format!("a\nb {}{}\nc\n", "(".repeat(1001), ")".repeat(1001));
+  for code in cases.into_iter().chain([deep.as_str()]) {
+    for threads in [1, 2, 4] {
+      assert_eq!(parse_parallel_with_threads(code, threads), parse(code), "parsing {code:?}");
+    }
+  }
+  // every prefix of the test files, which cuts strings and lists open
+  for (_, code) in common::cirru_files() {
+    for end in (0..code.len()).filter(|i| code.is_char_boundary(*i)).step_by(7) {
+      let code = &code[..end];
+      assert_eq!(parse_parallel_with_threads(code, 3), parse(code), "parsing {code:?}");
+    }
+  }
+}
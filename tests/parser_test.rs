@@ -71,3 +71,37 @@ mod json_test {
     Ok(())
   }
 }
+
+mod pipeline_test {
+  use cirru_parser::{Cirru, CirruError, build_exprs, lex, parse, resolve_comma, resolve_dollar, resolve_indentations};
+
+  /// the steps `parse` does in one pass, run one by one
+  fn parse_in_steps(code: &str) -> Result<Vec<Cirru>, CirruError> {
+    let tokens = resolve_indentations(&lex(code)?);
+    let mut tree = build_exprs(&tokens)?;
+    resolve_dollar(&mut tree);
+    resolve_comma(&mut tree);
+    Ok(tree)
+  }
+
+  #[test]
+  fn same_as_steps() {
+    // the test files are checked in fixtures_test, these are the cases where a single pass may differ
+    let cases = [
+      "",
+      "\n\n",
+      "a (b\nc",
+      "a)",
+      "a\n)",
+      "(a))\nb \"c",
+      "a\n    b\n  c\nd",
+      "  a\nb",
+      "a $\n  , b",
+      "a \"b\\",
+      "a\n   b",
+    ];
+    for code in cases {
+      assert_eq!(parse(code), parse_in_steps(code), "parsing {code:?}");
+    }
+  }
+}
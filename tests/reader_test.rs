@@ -0,0 +1,94 @@
+extern crate cirru_parser;
+
+use cirru_parser::{
+  CirruErrorKind, CirruWriterOptions, ParseOptions, ReaderBracket, ReaderPrefix, ReaderTable, format, parse, parse_with_options,
+};
+
+fn standard() -> ParseOptions {
+  ParseOptions {
+    reader: ReaderTable::STANDARD,
+    ..Default::default()
+  }
+}
+
+#[test]
+fn brackets_and_prefixes() -> Result<(), String> {
+  let options = standard();
+  let cases = [
+    ("a [b c] {d}", "a ([] b c) ({} d)"),
+    ("a [] {}", "a ([]) ({})"),
+    ("f [a [b]]", "f ([] a ([] b))"),
+    ("x[y]z", "x ([] y) z"),
+    ("a 'b", "a (quote b)"),
+    ("a '(b c) '[d]", "a (quote (b c)) (quote ([] d))"),
+    ("a ''b c", "a (quote (quote b)) c"),
+    ("a '\"b c\"", "a (quote \"b c\")"),
+    ("(a 'b)", "(a (quote b))"),
+    ("'a b", "(quote a) b"),
+    ("a\n  'b c", "a\n  (quote b) c"),
+    // a prefix that is not followed by a form, or inside a leaf, is a character of a leaf
+    ("a ' b'c", "a \"'\" \"b'c\""),
+    ("a (b ')", "a (b \"'\")"),
+    ("a $ [b] c", "a $ ([] b) c"),
+  ];
+  for (code, expected) in cases {
+    assert_eq!(parse_with_options(code, &options)?, parse(expected)?, "parsing {code:?}");
+  }
+  // off by default
+  assert_eq!(parse("a [b] 'c")?, parse("a \"[b]\" \"'c\"")?);
+  Ok(())
+}
+
+#[test]
+fn dialect_table() -> Result<(), String> {
+  let options = ParseOptions {
+    reader: ReaderTable {
+      brackets: &[ReaderBracket {
+        open: '<',
+        close: '>',
+        head: "tuple",
+      }],
+      prefixes: &[ReaderPrefix {
+        prefix: '@',
+        head: "deref",
+      }],
+    },
+    ..Default::default()
+  };
+  assert_eq!(parse_with_options("f <a @b> [c]", &options)?, parse("f (tuple a (deref b)) [c]")?);
+  Ok(())
+}
+
+#[test]
+fn mismatched_brackets() {
+  let options = standard();
+  let error = parse_with_options("a [b c)", &options).unwrap_err();
+  assert_eq!(error.kind, CirruErrorKind::UnmatchedParen);
+  assert_eq!(error.context.map(|c| c.pos.column), Some(7));
+
+  let error = parse_with_options("a {b]", &options).unwrap_err();
+  assert_eq!(error.kind, CirruErrorKind::UnmatchedParen);
+
+  assert!(parse_with_options("a [b", &options).is_err());
+  assert!(parse_with_options("a 'b]", &options).is_err());
+}
+
+#[test]
+fn write_sugared_forms() -> Result<(), String> {
+  let options = standard();
+  let writer_options = CirruWriterOptions::from(true).with_reader(ReaderTable::STANDARD);
+  let tree = parse("def v ([] 1 2) ({} a b) (quote c) (quote) ([] \"[x]\" \"'y\" z')")?;
+  let code = format(&tree, writer_options)?;
+  assert_eq!(code, "\ndef v [1 2] {a b} 'c (quote) [\"[x]\" \"'y\" z']\n");
+  assert_eq!(parse_with_options(&code, &options)?, tree);
+
+  let tree = parse("f (g (quote (a b)) ([]))")?;
+  let code = format(&tree, writer_options)?;
+  assert_eq!(parse_with_options(&code, &options)?, tree);
+
+  // leaves the reader would split are quoted even where lists are written as blocks
+  let tree = parse("[] a\n  quote b")?;
+  let code = format(&tree, writer_options)?;
+  assert_eq!(parse_with_options(&code, &options)?, tree);
+  Ok(())
+}
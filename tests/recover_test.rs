@@ -0,0 +1,64 @@
+extern crate cirru_parser;
+
+use cirru_parser::{Cirru, CirruErrorKind, parse, parse_recovering};
+
+#[test]
+fn recover_parens_closed_later() -> Result<(), String> {
+  // parentheses may close on a later top-level line
+  let code = "a (b\nc) d\ne";
+  assert_eq!(parse_recovering(code), (parse(code)?, vec![]));
+  Ok(())
+}
+
+#[test]
+fn recover_lexer_errors() {
+  let (tree, errors) = parse_recovering("a\n   b\n  c \"d\ne \"\\x\"\n)f g");
+  let kinds: Vec<_> = errors.iter().map(|e| e.kind.to_owned()).collect();
+  assert_eq!(
+    kinds,
+    vec![
+      CirruErrorKind::InvalidIndentation(3),
+      CirruErrorKind::UnexpectedNewlineInString,
+      CirruErrorKind::InvalidEscape('x'),
+      CirruErrorKind::UnexpectedCloseParen,
+    ]
+  );
+  assert_eq!(
+    tree,
+    vec![
+      Cirru::List(vec!["a".into(), vec!["b"].into(), vec!["c", "d"].into()]),
+      vec!["e", "x"].into(),
+      vec!["f", "g"].into(),
+    ]
+  );
+  assert!(errors.iter().all(|e| e.context.is_some()));
+}
+
+#[test]
+fn recover_unbalanced_parens() {
+  let (tree, errors) = parse_recovering("a (b c\nd e\nf g)) h\ni");
+  let kinds: Vec<_> = errors.iter().map(|e| e.kind.to_owned()).collect();
+  assert_eq!(kinds[0], CirruErrorKind::UnexpectedEof);
+  assert_eq!(errors[0].context.as_ref().map(|c| c.pos.line), Some(1));
+  assert_eq!(kinds[1], CirruErrorKind::UnexpectedCloseParen);
+  assert_eq!(kinds.len(), 3);
+  assert_eq!(
+    tree,
+    vec![
+      Cirru::List(vec!["a".into(), vec!["b", "c"].into()]),
+      vec!["d", "e"].into(),
+      vec!["f", "g"].into(),
+      vec!["h"].into(),
+      vec!["i"].into(),
+    ]
+  );
+
+  // each line that is never closed stays alone
+  let (tree, errors) = parse_recovering(&"a (\n".repeat(20000));
+  assert_eq!(tree.len(), 20000);
+  assert_eq!(errors.len(), 20000);
+
+  let (tree, errors) = parse_recovering("a \"b");
+  assert_eq!(errors.len(), 1);
+  assert_eq!(tree, vec![Cirru::from(vec!["a", "b"])]);
+}
@@ -0,0 +1,72 @@
+extern crate cirru_parser;
+
+use cirru_parser::{SpannedCirru, parse_with_spans};
+
+/// returns the source text covered by a node
+fn text_of<'a>(code: &'a str, node: &SpannedCirru) -> &'a str {
+  let span = node.span();
+  &code[span.start.offset..span.end.offset]
+}
+
+fn children(node: &SpannedCirru) -> &[SpannedCirru] {
+  match node {
+    SpannedCirru::List(xs, _) => xs,
+    SpannedCirru::Leaf(..) => panic!("expected list, got {node:?}"),
+  }
+}
+
+#[test]
+fn leaf_and_paren_spans() -> Result<(), String> {
+  let code = "a (b \"c d\")\n  e";
+  let tree = parse_with_spans(code)?;
+  assert_eq!(tree.len(), 1);
+  assert_eq!(text_of(code, &tree[0]), code);
+
+  let xs = children(&tree[0]);
+  assert_eq!(text_of(code, &xs[0]), "a");
+  assert_eq!(text_of(code, &xs[1]), "(b \"c d\")");
+  assert_eq!(text_of(code, &children(&xs[1])[1]), "\"c d\"");
+  assert_eq!(text_of(code, &xs[2]), "e");
+
+  let e = xs[2].span();
+  assert_eq!((e.start.line, e.start.column, e.start.offset), (2, 3, 14));
+  assert_eq!((e.end.line, e.end.column, e.end.offset), (2, 4, 15));
+  Ok(())
+}
+
+#[test]
+fn indentation_list_spans() -> Result<(), String> {
+  let code = "defn f\n  if a\n    b c\n\nd";
+  let tree = parse_with_spans(code)?;
+  assert_eq!(tree.len(), 2);
+  assert_eq!(text_of(code, &tree[0]), "defn f\n  if a\n    b c");
+  assert_eq!(text_of(code, &children(&tree[0])[2]), "if a\n    b c");
+  assert_eq!(text_of(code, &tree[1]), "d");
+  Ok(())
+}
+
+#[test]
+fn sugar_spans() -> Result<(), String> {
+  let code = "a $ b c\nd\n  , e f";
+  let tree = parse_with_spans(code)?;
+
+  let xs = children(&tree[0]);
+  assert_eq!(xs.len(), 2);
+  assert_eq!(text_of(code, &xs[1]), "$ b c");
+
+  let ys = children(&tree[1]);
+  assert_eq!(ys.len(), 3);
+  assert_eq!(text_of(code, &ys[1]), "e");
+  assert_eq!(text_of(code, &ys[2]), "f");
+  Ok(())
+}
+
+#[test]
+fn span_errors() {
+  let err = parse_with_spans("a (b\nc").unwrap_err();
+  let ctx = err.context.expect("position of unclosed paren");
+  assert_eq!((ctx.pos.line, ctx.pos.column), (1, 1));
+
+  let err = parse_with_spans("a) b").unwrap_err();
+  assert!(err.context.is_some());
+}
@@ -0,0 +1,87 @@
+extern crate cirru_parser;
+
+use std::io::{BufReader, Read};
+
+use cirru_parser::{Cirru, CirruErrorKind, CirruReader, parse};
+
+fn read_all(code: &str) -> Result<Vec<Cirru>, cirru_parser::CirruError> {
+  // a tiny buffer, so lines come in several reads
+  CirruReader::new(BufReader::with_capacity(3, code.as_bytes())).collect()
+}
+
+#[test]
+fn same_as_parse() -> Result<(), String> {
+  for code in [
+    "a (b\nc) d\ne",
+    "\n\na\n  b\n\nc $ d\n, e",
+    "a\r\nb\r\n",
+    "\u{feff}a b",
+    "a\n\u{feff}b\n",
+    "",
+  ] {
+    assert_eq!(read_all(code), parse(code), "reading {code:?}");
+  }
+  Ok(())
+}
+
+#[test]
+fn yields_finished_expressions() {
+  let code = "a 1\n  b\nc 2\nd (\n";
+  let mut reader = CirruReader::new(code.as_bytes());
+  assert_eq!(reader.next(), Some(Ok(parse("a 1\n  b").unwrap()[0].to_owned())));
+  assert_eq!(reader.next(), Some(Ok(parse("c 2").unwrap()[0].to_owned())));
+  assert!(matches!(reader.next(), Some(Err(_))));
+  assert_eq!(reader.next(), None);
+}
+
+#[test]
+fn error_positions() {
+  let cases = [
+    "a\nb\nc \"d\ne\n",
+    "a\nb (c\n  d\ne\n   f\n",
+    "a 1\nb \"\\x\"\n",
+    "alpha beta\ngamma\nd \"\\x\"\nepsilon\n",
+  ];
+  for code in cases {
+    let whole = parse(code).unwrap_err();
+    let streamed = read_all(code).unwrap_err();
+    assert_eq!(streamed.kind, whole.kind, "reading {code:?}");
+    // snippets show the input around the error, also across top-level expressions
+    assert_eq!(streamed.context, whole.context, "reading {code:?}");
+  }
+}
+
+#[test]
+fn stray_close_fails_early() {
+  let code = format!("a)\n{}", "b c\n".repeat(10000));
+  let whole = parse(&code).unwrap_err();
+  let mut rest = code.as_bytes();
+  let streamed = CirruReader::new(&mut rest).next().unwrap().unwrap_err();
+  assert_eq!(streamed.context.map(|c| c.pos), whole.context.map(|c| c.pos));
+  // the error comes before the rest of the input is read
+  assert!(rest.len() > 30000, "{} bytes left", rest.len());
+}
+
+struct FailingRead;
+
+impl Read for FailingRead {
+  fn read(&mut self, _buf: &mut [u8]) -> std::io::Result<usize> {
+    Err(std::io::Error::other("broken pipe"))
+  }
+}
+
+#[test]
+fn io_errors() {
+  let mut reader = CirruReader::from_read(FailingRead);
+  let error = reader.next().unwrap().unwrap_err();
+  assert!(matches!(error.kind, CirruErrorKind::Io(_)));
+  assert_eq!(reader.next(), None);
+
+  let error = read_all_bytes(b"a\n\xff\n").unwrap_err();
+  assert!(matches!(error.kind, CirruErrorKind::Io(_)));
+  assert_eq!(error.context.map(|c| c.pos.line), Some(2));
+}
+
+fn read_all_bytes(input: &[u8]) -> Result<Vec<Cirru>, cirru_parser::CirruError> {
+  CirruReader::new(input).collect()
+}
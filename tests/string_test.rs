@@ -0,0 +1,96 @@
+extern crate cirru_parser;
+
+use cirru_parser::{Cirru, CirruErrorKind, CirruWriterOptions, IndentUnit, ParseOptions, format, parse, parse_with_options};
+
+fn string_options() -> ParseOptions {
+  ParseOptions {
+    block_strings: true,
+    raw_strings: true,
+    ..Default::default()
+  }
+}
+
+#[test]
+fn block_strings() -> Result<(), String> {
+  let options = string_options();
+  let code = "def query \"\"\"\n  select *\n    from t\n\n  where 1\n\nprint query\n";
+  assert_eq!(
+    parse_with_options(code, &options)?,
+    vec![
+      Cirru::List(vec!["def".into(), "query".into(), "select *\n  from t\n\nwhere 1".into()]),
+      Cirru::List(vec!["print".into(), "query".into()]),
+    ]
+  );
+
+  // indented by the line of the marker, and ends at a line indented less
+  let code = "a\n  b \"\"\"\n    \"x\\n\" y\n  c\n";
+  assert_eq!(parse_with_options(code, &options)?, parse("a\n  b \"\\\"x\\\\n\\\" y\"\n  c")?);
+
+  let code = "a \"\"\"\nb";
+  assert_eq!(parse_with_options(code, &options)?, parse("a \"\"\nb")?);
+
+  let tabs = ParseOptions {
+    indent: IndentUnit::Tab,
+    ..options.to_owned()
+  };
+  assert_eq!(
+    parse_with_options("a\n\tb \"\"\"\n\t\t x\n\tc", &tabs)?,
+    parse("a\n  b \" x\"\n  c")?
+  );
+
+  let error = parse_with_options("a \"\"\" b\n  c", &options).unwrap_err();
+  assert_eq!(error.kind, CirruErrorKind::UnexpectedChar('b'));
+  assert_eq!(error.context.map(|c| c.pos.column), Some(7));
+
+  // off by default
+  assert_eq!(parse("a \"\"\"\"")?, parse("a \"\" \"\"")?);
+  Ok(())
+}
+
+#[test]
+fn block_string_positions() -> Result<(), String> {
+  let options = string_options();
+  let mut lexer = cirru_parser::Lexer::new("a \"\"\"\n  x\n  y\nb").with_options(&options);
+  let tokens = lexer.by_ref().collect::<Result<Vec<_>, _>>()?;
+  let (item, span) = &tokens[2];
+  assert_eq!(*item, "x\ny".into());
+  assert_eq!((span.start.offset, span.end.offset), (2, 13));
+  assert_eq!((span.end.line, span.end.column), (3, 4));
+  let (_, span) = &tokens[4];
+  assert_eq!((span.start.line, span.start.column), (4, 1));
+  Ok(())
+}
+
+#[test]
+fn raw_strings() -> Result<(), String> {
+  let options = string_options();
+  assert_eq!(
+    parse_with_options("match r\"\\d+\\.\\n\" r \"r\"", &options)?,
+    vec![Cirru::List(vec!["match".into(), "\\d+\\.\\n".into(), "r".into(), "r".into()])]
+  );
+  assert!(parse_with_options("a r\"b\nc\"", &options).is_err());
+  assert_eq!(parse("a r\"b\"")?, parse("a r b")?);
+  Ok(())
+}
+
+#[test]
+fn write_strings() -> Result<(), String> {
+  let options = string_options();
+  let writer_options = CirruWriterOptions::default().with_block_strings(true).with_raw_strings(true);
+  let tree = vec![
+    Cirru::List(vec!["def".into(), "a".into(), "line 1\n  line 2\n\nline 4".into()]),
+    Cirru::List(vec!["f".into(), "x\ny".into(), "C:\\my path".into()]),
+    Cirru::List(vec![
+      "g".into(),
+      Cirru::List(vec!["h".into(), Cirru::List(vec!["i".into()])]),
+      "p\nq".into(),
+    ]),
+    Cirru::List(vec!["trailing".into(), "a\n".into()]),
+  ];
+  let code = format(&tree, writer_options)?;
+  assert!(code.starts_with("\ndef a \"\"\"\n  line 1\n    line 2\n\n  line 4\n"), "{code}");
+  assert!(code.contains("r\"C:\\my path\""), "{code}");
+  assert!(code.contains("\"a\\n\""), "{code}");
+  assert_eq!(parse_with_options(&code, &options)?, tree);
+  Ok(())
+}
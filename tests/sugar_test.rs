@@ -0,0 +1,110 @@
+extern crate cirru_parser;
+
+mod common;
+
+use cirru_parser::{Cirru, build_exprs, lex, parse, resolve_comma, resolve_dollar, resolve_indentations};
+
+use common::Random;
+
+/// `resolve_comma` as it was written first, splicing one comma expression at a time
+fn reference_comma(xs: &mut Vec<Cirru>) {
+  loop {
+    let comma_pos = xs
+      .iter()
+      .position(|x| matches!(x, Cirru::List(ys) if ys.first().is_some_and(|y| y.eq_leaf(","))));
+    match comma_pos {
+      Some(p) => {
+        let Cirru::List(mut to_insert) = xs.remove(p) else {
+          unreachable!("comma expression is a list")
+        };
+        to_insert.remove(0);
+        for (i, item) in to_insert.into_iter().enumerate() {
+          xs.insert(p + i, item);
+        }
+      }
+      None => break,
+    }
+  }
+  xs.retain(|x| !x.eq_leaf(","));
+  for x in xs.iter_mut() {
+    if let Cirru::List(ys) = x {
+      reference_comma(ys);
+    }
+  }
+}
+
+/// `resolve_dollar` as it was written first, wrapping from the rightmost `$` again and again
+fn reference_dollar(xs: &mut Vec<Cirru>) {
+  for x in xs.iter_mut() {
+    if let Cirru::List(ys) = x {
+      reference_dollar(ys);
+    }
+  }
+  while let Some(p) = xs.iter().rposition(|x| x.eq_leaf("$")) {
+    let items: Vec<Cirru> = xs.drain(p + 1..).collect();
+    xs.pop();
+    xs.push(Cirru::List(items));
+  }
+}
+
+fn random_tree(random: &mut Random, depth: usize) -> Vec<Cirru> {
+  let len = random.below(6);
+  (0..len)
+    .map(|_| match random.below(if depth > 0 { 7 } else { 4 }) {
+      0 => Cirru::leaf("$"),
+      1 => Cirru::leaf(","),
+      2 | 3 => Cirru::leaf(["a", "b", "c"][random.below(3)]),
+      4 => {
+        let mut ys = vec![Cirru::leaf(",")];
+        ys.extend(random_tree(random, depth - 1));
+        Cirru::List(ys)
+      }
+      _ => Cirru::List(random_tree(random, depth - 1)),
+    })
+    .collect()
+}
+
+#[test]
+fn same_as_reference() {
+  let mut random = Random(5);
+  for _ in 0..20000 {
+    let tree = random_tree(&mut random, 4);
+
+    let (mut xs, mut ys) = (tree.to_owned(), tree.to_owned());
+    resolve_comma(&mut xs);
+    reference_comma(&mut ys);
+    assert_eq!(xs, ys, "resolving commas in {tree:?}");
+
+    let (mut xs, mut ys) = (tree.to_owned(), tree.to_owned());
+    resolve_dollar(&mut xs);
+    reference_dollar(&mut ys);
+    assert_eq!(xs, ys, "resolving dollars in {tree:?}");
+
+    resolve_comma(&mut xs);
+    reference_comma(&mut ys);
+    assert_eq!(xs, ys, "resolving both in {tree:?}");
+  }
+}
+
+#[test]
+fn parse_same_as_reference() {
+  for (_, code) in common::cirru_files() {
+    let mut expected = build_exprs(&resolve_indentations(&lex(&code).unwrap())).unwrap();
+    reference_dollar(&mut expected);
+    reference_comma(&mut expected);
+    assert_eq!(parse(&code).unwrap(), expected);
+  }
+}
+
+#[test]
+fn long_lists() {
+  // a table with many `,` rows, and a long chain of `$`
+  let rows = 20000;
+  let code = format!("table\n{}", "  , a b c\n".repeat(rows));
+  let tree = parse(&code).unwrap();
+  assert_eq!(tree[0].len(), 1 + rows * 3);
+
+  let code = format!("f{}", " a $".repeat(rows / 40));
+  let tree = parse(&code).unwrap();
+  assert_eq!(tree[0].len(), 3);
+}
@@ -12,7 +12,7 @@ mod json_write_test {
 
   #[test]
   fn write_demo() -> Result<(), String> {
-    let writer_options = CirruWriterOptions { use_inline: false };
+    let writer_options = CirruWriterOptions::from(false);
 
     match from_json_str(r#"[["a"], ["b"]]"#) {
       Ok(tree) => {
@@ -68,7 +68,7 @@ mod json_write_test {
       let json_str = fs::read_to_string(format!("./tests/writer_data/{file}.json"))?;
       let cirru_str = fs::read_to_string(format!("./tests/writer_cirru/{file}.cirru"))?;
 
-      let writer_options = CirruWriterOptions { use_inline: false };
+      let writer_options = CirruWriterOptions::from(false);
       match from_json_str(&json_str) {
         Ok(tree) => {
           if let Cirru::List(xs) = tree {
@@ -94,7 +94,7 @@ mod json_write_test {
       let json_str = fs::read_to_string(format!("./tests/writer_data/{file}.json"))?;
       let cirru_str = fs::read_to_string(format!("./tests/writer_cirru/{file}.cirru"))?;
 
-      let writer_options = CirruWriterOptions { use_inline: true };
+      let writer_options = CirruWriterOptions::from(true);
       match from_json_str(&json_str) {
         Ok(tree) => {
           if let Cirru::List(xs) = tree {
@@ -209,7 +209,7 @@ fn test_dollar_sign_spacing() -> Result<(), String> {
     ]
   ]"#;
 
-  let writer_options = CirruWriterOptions { use_inline: false };
+  let writer_options = CirruWriterOptions::from(false);
 
   match from_json_str(json_str) {
     Ok(tree) => {
@@ -233,3 +233,20 @@ fn test_dollar_sign_spacing() -> Result<(), String> {
     }
   }
 }
+
+#[test]
+fn unicode_escapes_round_trip() -> Result<(), String> {
+  use cirru_parser::{Cirru, CirruWriterOptions, escape_cirru_leaf_with, format, parse};
+
+  let leaf = "a\u{200b}b\u{1}\r\u{feff}中文";
+  let xs = vec![Cirru::List(vec![Cirru::leaf("println"), Cirru::leaf(leaf)])];
+  let options = CirruWriterOptions::default().with_unicode_escapes(true);
+  let rendered = format(&xs, options)?;
+  assert_eq!("\nprintln \"a\\u{200b}b\\u{1}\\r\\u{feff}中文\"\n", rendered);
+  assert_eq!(parse(&rendered)?, xs);
+
+  // without the option, characters are written as they are
+  assert_eq!(format(&xs, CirruWriterOptions::from(false))?, format!("\nprintln \"{leaf}\"\n"));
+  assert_eq!(escape_cirru_leaf_with("\t\u{7f}", true), "\"\\t\\u{7f}\"");
+  Ok(())
+}